@@ -0,0 +1,169 @@
+//! `#[derive(KeyOrd)]`: PartialEq, Eq, Hash, PartialOrd and Ord from the `#[key]` fields only.
+//!
+//! Key fields are compared in declaration order. Each may be:
+//!
+//! - `#[key]`: compared with `Ord`, hashed with `Hash`
+//! - `#[key(reverse)]`: compared in reverse
+//! - `#[key(cmp = path)]`: compared with `fn(&T, &T) -> Ordering`, and left out of `Hash`
+//!   (hashing fewer fields than are compared is still consistent with `Eq`)
+//!
+//! ```
+//! use key_ord::KeyOrd;
+//!
+//! #[derive(Debug, KeyOrd)]
+//! struct Reading {
+//!     #[key]
+//!     sensor: &'static str,
+//!     #[key(reverse)]
+//!     time: u64,
+//!     value: f64,
+//! }
+//!
+//! let mut v = [
+//!     Reading { sensor: "b", time: 1, value: 0.5 },
+//!     Reading { sensor: "a", time: 1, value: 1.5 },
+//!     Reading { sensor: "a", time: 2, value: f64::NAN },
+//! ];
+//! v.sort();
+//! assert_eq!(v.map(|r| (r.sensor, r.time)), [("a", 2), ("a", 1), ("b", 1)]);
+//! ```
+//!
+//! A `#[key]` field must be `Ord` and `Hash`:
+//!
+//! ```compile_fail
+//! use key_ord::KeyOrd;
+//!
+//! #[derive(KeyOrd)]
+//! struct KeyValue {
+//!     #[key]
+//!     value: f64,
+//! }
+//! ```
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned, ToTokens};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, Index, Member, Path};
+
+struct KeyField {
+    member: Member,
+    reverse: bool,
+    cmp: Option<Path>,
+}
+
+#[proc_macro_derive(KeyOrd, attributes(key))]
+pub fn derive_key_ord(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand(mut input: DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(s) => &s.fields,
+        _ => return Err(Error::new(input.ident.span(), "KeyOrd can only be derived for structs")),
+    };
+
+    let mut keys = vec![];
+    let mut bounds = vec![];
+    for (i, field) in fields.iter().enumerate() {
+        let Some(attr) = field.attrs.iter().find(|a| a.path().is_ident("key")) else {
+            continue;
+        };
+
+        let mut key = KeyField {
+            member: match &field.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(Index::from(i)),
+            },
+            reverse: false,
+            cmp: None,
+        };
+        // Bare #[key] has no arguments to parse
+        if !matches!(attr.meta, syn::Meta::Path(_)) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("reverse") {
+                    key.reverse = true;
+                    Ok(())
+                } else if meta.path.is_ident("cmp") {
+                    key.cmp = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `reverse` or `cmp = path`"))
+                }
+            })?;
+        }
+
+        // Spanned so a missing Ord or Hash is reported at the field
+        if key.cmp.is_none() {
+            let ty = &field.ty;
+            bounds.push(quote_spanned! { ty.span() => #ty: ::core::cmp::Ord + ::core::hash::Hash });
+        }
+        keys.push(key);
+    }
+
+    if keys.is_empty() {
+        let span = match fields {
+            Fields::Unit => input.ident.span(),
+            _ => fields.span(),
+        };
+        return Err(Error::new(span, "KeyOrd needs at least one #[key] field"));
+    }
+
+    let where_clause = input.generics.make_where_clause();
+    for bound in bounds {
+        where_clause.predicates.push(parse_quote!(#bound));
+    }
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let eqs = keys.iter().map(|k| {
+        let m = &k.member;
+        match &k.cmp {
+            Some(cmp) => quote! { #cmp(&self.#m, &other.#m) == ::core::cmp::Ordering::Equal },
+            None => quote! { self.#m == other.#m },
+        }
+    });
+
+    let cmps = keys.iter().map(|k| {
+        let m = &k.member;
+        let (a, b) = if k.reverse { (quote!(other), quote!(self)) } else { (quote!(self), quote!(other)) };
+        let cmp = match &k.cmp {
+            Some(cmp) => cmp.to_token_stream(),
+            None => quote!(::core::cmp::Ord::cmp),
+        };
+        quote! { .then_with(|| #cmp(&#a.#m, &#b.#m)) }
+    });
+
+    let hashes = keys.iter().filter(|k| k.cmp.is_none()).map(|k| {
+        let m = &k.member;
+        quote! { ::core::hash::Hash::hash(&self.#m, state); }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::core::cmp::PartialEq for #name #ty_generics #where_clause {
+            fn eq(&self, other: &Self) -> bool {
+                true #(&& #eqs)*
+            }
+        }
+
+        impl #impl_generics ::core::cmp::Eq for #name #ty_generics #where_clause {}
+
+        impl #impl_generics ::core::hash::Hash for #name #ty_generics #where_clause {
+            fn hash<H: ::core::hash::Hasher>(&self, state: &mut H) {
+                #(#hashes)*
+            }
+        }
+
+        impl #impl_generics ::core::cmp::PartialOrd for #name #ty_generics #where_clause {
+            fn partial_cmp(&self, other: &Self) -> ::core::option::Option<::core::cmp::Ordering> {
+                ::core::option::Option::Some(::core::cmp::Ord::cmp(self, other))
+            }
+        }
+
+        impl #impl_generics ::core::cmp::Ord for #name #ty_generics #where_clause {
+            fn cmp(&self, other: &Self) -> ::core::cmp::Ordering {
+                ::core::cmp::Ordering::Equal #(#cmps)*
+            }
+        }
+    })
+}
@@ -1,5 +1,12 @@
 #![allow(unused)]
 
+mod bus;
+mod sync;
+
+use std::cell::RefCell;
+use std::mem;
+use std::rc::{Rc, Weak};
+
 #[derive(Debug)]
 enum Event {
     Fire(u32)
@@ -7,27 +14,160 @@ enum Event {
 
 type Callback<'c> = dyn FnMut(&Event) + 'c;
 
+/// Names a slot and which of its occupants: a slot's generation goes up as its callback is
+/// deregistered, so older handles to it no longer match
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct StaleHandle(Handle);
+
+impl std::fmt::Display for StaleHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stale or unknown callback handle {:?}", self.0)
+    }
+}
+
+impl std::error::Error for StaleHandle {}
+
+struct Slot<'c> {
+    generation: u32,
+    callback: Option<Box<Callback<'c>>>,
+}
+
+/// Handles of dropped Subscriptions, for the dispatcher to deregister. Shared rather than
+/// borrowing the dispatcher, which may be mid-dispatch with the guard dropped by a callback.
+type Dropped = Rc<RefCell<Vec<Handle>>>;
+
 struct Dispatcher<'c> {
-    callbacks: Vec<Option<Box<Callback<'c>>>>
+    slots: Vec<Slot<'c>>,
+    /// Empty slots, reused before growing
+    free: Vec<u32>,
+    /// Registration order for dispatch. Deregistered handles are left in place until they
+    /// are half of it, then swept, so deregister stays O(1) amortised.
+    order: Vec<Handle>,
+    dropped: Dropped,
 }
 
 impl<'c> Dispatcher<'c> {
     fn new() -> Self {
-        Self { callbacks: Vec::new() }
+        Self { slots: Vec::new(), free: Vec::new(), order: Vec::new(), dropped: Dropped::default() }
+    }
+
+    fn register(&mut self, callback: Box<Callback<'c>>) -> Handle {
+        self.remove_dropped();
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot { generation: 0, callback: None });
+                u32::try_from(self.slots.len() - 1).expect("too many callbacks")
+            }
+        };
+
+        let slot = &mut self.slots[index as usize];
+        slot.callback = Some(callback);
+        let handle = Handle { index, generation: slot.generation };
+        self.order.push(handle);
+        handle
+    }
+
+    /// Like register, but deregistered when the guard is dropped
+    fn subscribe(&mut self, callback: Box<Callback<'c>>) -> Subscription {
+        let handle = self.register(callback);
+        Subscription { handle, dropped: Rc::downgrade(&self.dropped) }
+    }
+
+    fn deregister(&mut self, handle: Handle) -> Result<(), StaleHandle> {
+        self.remove_dropped();
+        self.remove(handle)?;
+        self.sweep();
+        Ok(())
+    }
+
+    /// Deregisters without touching `order`, so it's safe mid-dispatch
+    fn remove(&mut self, handle: Handle) -> Result<(), StaleHandle> {
+        if !self.is_live(handle) {
+            return Err(StaleHandle(handle));
+        }
+
+        let slot = &mut self.slots[handle.index as usize];
+        slot.callback = None;
+        // Wraps after 2^32 reuses of one slot, when a very old handle could match again
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        Ok(())
     }
 
-    fn register(&mut self, callback: Box<Callback<'c>>) -> usize {
-        self.callbacks.push(Some(callback));
-        self.callbacks.len() - 1
+    fn remove_dropped(&mut self) {
+        let dropped = mem::take(&mut *self.dropped.borrow_mut());
+        for handle in dropped {
+            // Already deregistered by hand, after Subscription::handle
+            let _ = self.remove(handle);
+        }
     }
 
-    fn deregister(&mut self, index: usize) {
-        self.callbacks[index] = None;
+    fn sweep(&mut self) {
+        if self.order.len() > 2 * self.len() {
+            let slots = &self.slots;
+            self.order.retain(|h| slots[h.index as usize].generation == h.generation);
+        }
+    }
+
+    /// Registered callbacks, counting dropped Subscriptions as gone
+    fn len(&self) -> usize {
+        let dropped = self.dropped.borrow().iter().filter(|&&h| self.is_live(h)).count();
+        self.slots.len() - self.free.len() - dropped
+    }
+
+    fn is_live(&self, handle: Handle) -> bool {
+        self.slots.get(handle.index as usize)
+            .is_some_and(|slot| slot.generation == handle.generation && slot.callback.is_some())
     }
 
     fn dispatch(&mut self, event: &Event) {
-        for callback in self.callbacks.iter_mut().flatten() {
-            (*callback)(event);
+        // By index: callbacks can drop Subscriptions, which are removed before the next call
+        for i in 0..self.order.len() {
+            self.remove_dropped();
+            let handle = self.order[i];
+            let slot = &mut self.slots[handle.index as usize];
+            if slot.generation == handle.generation {
+                if let Some(callback) = &mut slot.callback {
+                    callback(event);
+                }
+            }
+        }
+        self.remove_dropped();
+        self.sweep();
+    }
+}
+
+/// Deregisters its callback when dropped, or does nothing if the dispatcher has gone
+#[must_use = "dropping a Subscription deregisters its callback at once"]
+struct Subscription {
+    handle: Handle,
+    dropped: Weak<RefCell<Vec<Handle>>>,
+}
+
+impl Subscription {
+    fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// Leaves the callback registered for the dispatcher's lifetime, or until deregistered by
+    /// the returned handle
+    fn detach(mut self) -> Handle {
+        self.dropped = Weak::new();
+        self.handle
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(dropped) = self.dropped.upgrade() {
+            dropped.borrow_mut().push(self.handle);
         }
     }
 }
@@ -35,7 +175,7 @@ impl<'c> Dispatcher<'c> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::cell::Cell;
+    use std::cell::{Cell, RefCell};
 
     #[test]
     fn callback() {
@@ -62,16 +202,120 @@ mod tests {
         }
         assert_eq!(count.get(), N);
 
-        dispatcher.deregister(dbg_handler);
+        dispatcher.deregister(dbg_handler).unwrap();
         for _ in 0..N {
             let event = Event::Fire(2);
             dispatcher.dispatch(&event);
         }
         assert_eq!(count.get(), 3 * N);
 
-        dispatcher.deregister(count_handler);
+        dispatcher.deregister(count_handler).unwrap();
         dispatcher.dispatch(&Event::Fire(100));
 
         assert_eq!(count.get(), 3 * N);
     }
+
+    #[test]
+    fn stale_handles() {
+        let mut dispatcher = Dispatcher::new();
+        let a = dispatcher.register(Box::new(|_: &Event| {}));
+        dispatcher.deregister(a).unwrap();
+        assert_eq!(dispatcher.deregister(a), Err(StaleHandle(a)));
+
+        // a's slot is reused, but a still doesn't name it
+        let b = dispatcher.register(Box::new(|_: &Event| {}));
+        assert_eq!(b.index, a.index);
+        assert_eq!(dispatcher.deregister(a), Err(StaleHandle(a)));
+        assert_eq!(dispatcher.len(), 1);
+
+        let unknown = Handle { index: 100, generation: 0 };
+        assert!(dispatcher.deregister(unknown).is_err());
+        dispatcher.deregister(b).unwrap();
+        assert_eq!(dispatcher.len(), 0);
+    }
+
+    #[test]
+    fn churn() {
+        let log = RefCell::new(vec![]);
+        let mut dispatcher = Dispatcher::new();
+
+        let handles: Vec<_> = (0..4).map(|i| {
+            let log = &log;
+            dispatcher.register(Box::new(move |_: &Event| log.borrow_mut().push(i)))
+        }).collect();
+        dispatcher.deregister(handles[1]).unwrap();
+        dispatcher.deregister(handles[2]).unwrap();
+        // Into the freed slots, but dispatched after the older ones
+        let log_ref = &log;
+        dispatcher.register(Box::new(move |_: &Event| log_ref.borrow_mut().push(4)));
+        dispatcher.register(Box::new(move |_: &Event| log_ref.borrow_mut().push(5)));
+
+        dispatcher.dispatch(&Event::Fire(1));
+        assert_eq!(*log.borrow(), [0, 3, 4, 5]);
+        assert_eq!(dispatcher.slots.len(), 4);
+
+        // Register and drop constantly: nothing grows
+        for _ in 0..1000 {
+            let h = dispatcher.register(Box::new(|_: &Event| {}));
+            dispatcher.deregister(h).unwrap();
+        }
+        assert_eq!(dispatcher.slots.len(), 5);
+        assert!(dispatcher.order.len() <= 2 * dispatcher.len() + 1);
+    }
+
+    #[test]
+    fn subscriptions() {
+        let count = Cell::new(0);
+        let mut dispatcher = Dispatcher::new();
+
+        let sub = dispatcher.subscribe(Box::new(|_: &Event| count.set(count.get() + 1)));
+        dispatcher.dispatch(&Event::Fire(1));
+        drop(sub);
+        assert_eq!(dispatcher.len(), 0);
+        dispatcher.dispatch(&Event::Fire(1));
+        assert_eq!(count.get(), 1);
+
+        // Detached: registered until deregistered by hand
+        let handle = dispatcher.subscribe(Box::new(|_: &Event| count.set(count.get() + 1))).detach();
+        dispatcher.dispatch(&Event::Fire(1));
+        assert_eq!(count.get(), 2);
+        dispatcher.deregister(handle).unwrap();
+
+        // Deregistered by hand, then dropped
+        let sub = dispatcher.subscribe(Box::new(|_: &Event| {}));
+        dispatcher.deregister(sub.handle()).unwrap();
+        drop(sub);
+        assert_eq!(dispatcher.len(), 0);
+    }
+
+    #[test]
+    fn drop_during_dispatch() {
+        // The second callback drops the first's guard, and the third's
+        let log = RefCell::new(vec![]);
+        let subs = RefCell::new(vec![]);
+
+        let mut dispatcher = Dispatcher::new();
+        let first = dispatcher.subscribe(Box::new(|_: &Event| log.borrow_mut().push(1)));
+        let second = dispatcher.subscribe(Box::new(|_: &Event| {
+            log.borrow_mut().push(2);
+            subs.borrow_mut().clear();
+        }));
+        let third = dispatcher.subscribe(Box::new(|_: &Event| log.borrow_mut().push(3)));
+        subs.borrow_mut().extend([first, third]);
+
+        dispatcher.dispatch(&Event::Fire(1));
+        assert_eq!(*log.borrow(), [1, 2]);
+        dispatcher.dispatch(&Event::Fire(1));
+        assert_eq!(*log.borrow(), [1, 2, 2]);
+        assert_eq!(dispatcher.len(), 1);
+        drop(second);
+    }
+
+    #[test]
+    fn outlives_dispatcher() {
+        let mut dispatcher = Dispatcher::new();
+        let sub = dispatcher.subscribe(Box::new(|_: &Event| {}));
+        drop(dispatcher);
+        drop(sub);
+    }
 }
@@ -0,0 +1,157 @@
+/*
+ * An event bus for any number of event types, with no central enum to edit.
+ *
+ * Handlers are stored by the TypeId of the event type they take, so publishing an E only
+ * visits E's handlers, in registration order. Each is type-erased to FnMut(&dyn Any) and
+ * downcasts back to E, which can't fail as the TypeId matched.
+ *
+ * Filters narrow that further: a predicate on the event, or for enum events one variant,
+ * compared by mem::discriminant. TypeId needs 'static, so events can't borrow.
+ */
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::mem::{self, Discriminant};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct HandlerId {
+    type_id: TypeId,
+    seq: u64,
+}
+
+type Handler<'c> = Box<dyn FnMut(&dyn Any) + 'c>;
+
+#[derive(Default)]
+pub struct EventBus<'c> {
+    handlers: HashMap<TypeId, Vec<(u64, Handler<'c>)>>,
+    next_seq: u64,
+}
+
+impl<'c> EventBus<'c> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every E
+    pub fn subscribe<E: 'static>(&mut self, mut handler: impl FnMut(&E) + 'c) -> HandlerId {
+        self.push(TypeId::of::<E>(), Box::new(move |event| {
+            handler(event.downcast_ref().expect("handler for a different type"))
+        }))
+    }
+
+    /// Each E for which `filter` is true
+    pub fn subscribe_filtered<E: 'static>(
+        &mut self,
+        filter: impl Fn(&E) -> bool + 'c,
+        mut handler: impl FnMut(&E) + 'c,
+    ) -> HandlerId {
+        self.subscribe(move |event: &E| {
+            if filter(event) {
+                handler(event);
+            }
+        })
+    }
+
+    /// Only events of the same enum variant as `variant`, whatever their fields
+    pub fn subscribe_variant<E: 'static>(&mut self, variant: &E, handler: impl FnMut(&E) + 'c) -> HandlerId {
+        let discriminant: Discriminant<E> = mem::discriminant(variant);
+        self.subscribe_filtered(move |event| mem::discriminant(event) == discriminant, handler)
+    }
+
+    fn push(&mut self, type_id: TypeId, handler: Handler<'c>) -> HandlerId {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.handlers.entry(type_id).or_default().push((seq, handler));
+        HandlerId { type_id, seq }
+    }
+
+    /// False if it was already unsubscribed
+    pub fn unsubscribe(&mut self, id: HandlerId) -> bool {
+        let Some(handlers) = self.handlers.get_mut(&id.type_id) else {
+            return false;
+        };
+        // Sorted by seq, being pushed in order
+        match handlers.binary_search_by_key(&id.seq, |&(seq, _)| seq) {
+            Ok(i) => {
+                handlers.remove(i);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Calls E's handlers in the order they subscribed. Returns how many there were,
+    /// including any whose filter didn't match.
+    pub fn publish<E: 'static>(&mut self, event: &E) -> usize {
+        let Some(handlers) = self.handlers.get_mut(&TypeId::of::<E>()) else {
+            return 0;
+        };
+        for (_, handler) in handlers.iter_mut() {
+            handler(event);
+        }
+        handlers.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    struct Fire(u32);
+
+    struct Shutdown;
+
+    #[derive(Debug, PartialEq)]
+    enum Key {
+        Down(char),
+        Up(char),
+    }
+
+    #[test]
+    fn by_type() {
+        let fired = Cell::new(0);
+        let shutdowns = Cell::new(0);
+
+        let mut bus = EventBus::new();
+        let fire = bus.subscribe(|Fire(n): &Fire| fired.set(fired.get() + n));
+        bus.subscribe(|_: &Shutdown| shutdowns.set(shutdowns.get() + 1));
+
+        assert_eq!(bus.publish(&Fire(2)), 1);
+        assert_eq!(bus.publish(&Shutdown), 1);
+        // Nobody listens for these
+        assert_eq!(bus.publish(&"fire"), 0);
+        assert_eq!((fired.get(), shutdowns.get()), (2, 1));
+
+        assert!(bus.unsubscribe(fire));
+        assert!(!bus.unsubscribe(fire));
+        assert_eq!(bus.publish(&Fire(2)), 0);
+        assert_eq!(fired.get(), 2);
+    }
+
+    #[test]
+    fn filtered() {
+        let log = RefCell::new(vec![]);
+
+        let mut bus = EventBus::new();
+        bus.subscribe_variant(&Key::Down(' '), |k| log.borrow_mut().push(format!("down {k:?}")));
+        bus.subscribe_filtered(|k: &Key| matches!(k, Key::Up('q') | Key::Down('q')), |k| {
+            log.borrow_mut().push(format!("q {k:?}"))
+        });
+        bus.subscribe(|Fire(n): &Fire| log.borrow_mut().push(format!("fire {n}")));
+
+        bus.publish(&Key::Down('a'));
+        bus.publish(&Key::Up('a'));
+        bus.publish(&Fire(1));
+        bus.publish(&Key::Up('q'));
+        bus.publish(&Key::Down('q'));
+
+        // In order of publishing, then of subscribing
+        assert_eq!(*log.borrow(), [
+            "down Down('a')",
+            "fire 1",
+            "q Up('q')",
+            "down Down('q')",
+            "q Down('q')",
+        ]);
+    }
+}
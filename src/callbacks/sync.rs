@@ -0,0 +1,237 @@
+/*
+ * Dispatcher for callbacks shared between threads: register, deregister and dispatch from
+ * any thread through &SyncDispatcher (put it in an Arc).
+ *
+ * Callbacks are Fn + Send + Sync rather than FnMut, as two threads may dispatch at once;
+ * state goes in atomics or the callback's own Mutex. The list is copy-on-write behind a
+ * Mutex held only to clone or swap the Arc, never while callbacks run, so a callback can
+ * register, deregister or dispatch without deadlock, and a panicking one poisons nothing.
+ * A dispatch sees the callbacks registered when it started.
+ *
+ * Queue is the asynchronous side: events are sent down a channel to one worker thread,
+ * which dispatches them in the order sent. Dropping it delivers what's queued and joins.
+ */
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use super::Event;
+
+type SyncCallback = dyn Fn(&Event) + Send + Sync;
+
+/// Never reused, so a stale one can't match a newer callback
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct SyncHandle(u64);
+
+/// In registration order
+type Callbacks = Vec<(SyncHandle, Arc<SyncCallback>)>;
+
+#[derive(Default)]
+struct SyncDispatcher {
+    callbacks: Mutex<Arc<Callbacks>>,
+    next_id: AtomicU64,
+}
+
+impl SyncDispatcher {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn register(&self, callback: impl Fn(&Event) + Send + Sync + 'static) -> SyncHandle {
+        let handle = SyncHandle(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let mut callbacks = self.callbacks.lock().unwrap();
+        // Copies only if a dispatch is using the current list
+        Arc::make_mut(&mut callbacks).push((handle, Arc::new(callback)));
+        handle
+    }
+
+    /// False if it wasn't registered. A dispatch already under way may still call it.
+    fn deregister(&self, handle: SyncHandle) -> bool {
+        let mut callbacks = self.callbacks.lock().unwrap();
+        let Some(i) = callbacks.iter().position(|&(h, _)| h == handle) else {
+            return false;
+        };
+        let removed = Arc::make_mut(&mut callbacks).remove(i);
+        // Dropped after unlocking: what the callback captured may use the dispatcher in its Drop
+        drop(callbacks);
+        drop(removed);
+        true
+    }
+
+    fn len(&self) -> usize {
+        self.callbacks.lock().unwrap().len()
+    }
+
+    /// Runs the callbacks on this thread
+    fn dispatch(&self, event: &Event) {
+        let callbacks = Arc::clone(&self.callbacks.lock().unwrap());
+        for (_, callback) in callbacks.iter() {
+            callback(event);
+        }
+    }
+
+    /// Starts a worker thread dispatching queued events
+    fn queue(self: &Arc<Self>) -> Queue {
+        let (sender, receiver) = mpsc::channel::<Event>();
+        let dispatcher = Arc::clone(self);
+        let worker = thread::Builder::new()
+            .name("dispatch".to_string())
+            .spawn(move || {
+                for event in receiver {
+                    dispatcher.dispatch(&event);
+                }
+            })
+            .expect("spawn dispatch thread");
+        Queue { sender: Some(sender), worker: Some(worker) }
+    }
+}
+
+struct Queue {
+    /// Both Some until dropped
+    sender: Option<Sender<Event>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Queue {
+    /// Returns the event back if the worker has stopped, i.e. a callback panicked
+    fn post(&self, event: Event) -> Result<(), Event> {
+        let sender = self.sender.as_ref().expect("sender until dropped");
+        sender.send(event).map_err(|e| e.0)
+    }
+
+    /// Waits for queued events to be dispatched. Err has the panic of a callback that
+    /// stopped the worker.
+    fn join(mut self) -> thread::Result<()> {
+        self.sender = None;
+        self.worker.take().expect("worker until dropped").join()
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            // A callback's panic was already reported on the worker
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+    use std::sync::Weak;
+
+    #[test]
+    fn threads() {
+        const THREADS: u32 = 4;
+        const N: u32 = 1000;
+
+        let count = Arc::new(AtomicU32::new(0));
+        let dispatcher = SyncDispatcher::new();
+        let c = Arc::clone(&count);
+        dispatcher.register(move |Event::Fire(n)| {
+            c.fetch_add(*n, Ordering::Relaxed);
+        });
+
+        thread::scope(|scope| {
+            for _ in 0..THREADS {
+                scope.spawn(|| {
+                    for _ in 0..N {
+                        dispatcher.dispatch(&Event::Fire(1));
+                    }
+                });
+            }
+            // Registering from another thread meanwhile
+            scope.spawn(|| {
+                let h = dispatcher.register(|_| {});
+                assert!(dispatcher.deregister(h));
+                assert!(!dispatcher.deregister(h));
+            });
+        });
+        assert_eq!(count.load(Ordering::Relaxed), THREADS * N);
+        assert_eq!(dispatcher.len(), 1);
+    }
+
+    #[test]
+    fn reentrant() {
+        // Callbacks can use the dispatcher: no lock is held while they run
+        let dispatcher = Arc::new(SyncDispatcher::new());
+        let weak = Arc::downgrade(&dispatcher);
+        let seen = Arc::new(Mutex::new(vec![]));
+
+        let s = Arc::clone(&seen);
+        dispatcher.register(move |&Event::Fire(n)| {
+            s.lock().unwrap().push(n);
+            let d = weak.upgrade().unwrap();
+            if n > 0 {
+                d.dispatch(&Event::Fire(n - 1));
+            }
+            d.register(|_| {});
+        });
+
+        dispatcher.dispatch(&Event::Fire(2));
+        assert_eq!(*seen.lock().unwrap(), [2, 1, 0]);
+        // Each dispatch saw only the callbacks registered before it started
+        assert_eq!(dispatcher.len(), 4);
+    }
+
+    #[test]
+    fn deregister_drop_reentrant() {
+        // Registers another callback when dropped
+        struct OnDrop(Weak<SyncDispatcher>);
+
+        impl Drop for OnDrop {
+            fn drop(&mut self) {
+                if let Some(d) = self.0.upgrade() {
+                    d.register(|_| {});
+                }
+            }
+        }
+
+        let dispatcher = Arc::new(SyncDispatcher::new());
+        let on_drop = OnDrop(Arc::downgrade(&dispatcher));
+        let h = dispatcher.register(move |_| {
+            let _ = &on_drop;
+        });
+
+        assert!(dispatcher.deregister(h));
+        assert_eq!(dispatcher.len(), 1);
+    }
+
+    #[test]
+    fn queued() {
+        let dispatcher = Arc::new(SyncDispatcher::new());
+        let seen = Arc::new(Mutex::new(vec![]));
+        let s = Arc::clone(&seen);
+        dispatcher.register(move |&Event::Fire(n)| {
+            assert_eq!(thread::current().name(), Some("dispatch"));
+            s.lock().unwrap().push(n);
+        });
+
+        let queue = dispatcher.queue();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for n in 0..100 {
+                    queue.post(Event::Fire(n)).unwrap();
+                }
+            });
+        });
+        queue.join().unwrap();
+        assert_eq!(*seen.lock().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn queued_panic() {
+        let dispatcher = Arc::new(SyncDispatcher::new());
+        dispatcher.register(|_| panic!("callback"));
+
+        let queue = dispatcher.queue();
+        queue.post(Event::Fire(1)).unwrap();
+        assert!(queue.join().is_err());
+        // The dispatcher is still usable
+        assert_eq!(dispatcher.len(), 1);
+    }
+}
@@ -1,3 +1,6 @@
+pub(crate) const ISO_8601: &str = "%Y-%m-%dT%H:%M:%S";
+pub(crate) const ISO_8601_TZ: &str = "%Y-%m-%dT%H:%M:%S%z";
+
 #[cfg(test)]
 mod tests {
     use std::borrow::Borrow;
@@ -10,8 +13,8 @@ mod tests {
     use chrono::Offset;
     use chrono_tz::{America, Asia, Europe, Tz};
 
-    const ISO_8601: &str = "%Y-%m-%dT%H:%M:%S";
-    const ISO_8601_TZ: &str = "%Y-%m-%dT%H:%M:%S%z";
+    use super::{ISO_8601, ISO_8601_TZ};
+
     const ZERO: Duration = Duration::from_nanos(0);
 
     #[test]
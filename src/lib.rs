@@ -3,6 +3,7 @@
 #![feature(ptr_metadata)]
 #![feature(ptr_addr_eq)]
 #![feature(slice_as_chunks)]
+#![cfg_attr(test, feature(test))]
 
 mod callbacks;
 mod datetime;
@@ -11,5 +12,5 @@ mod io;
 mod lang;
 mod macros;
 mod serde;
-mod sort;
+pub mod sort;
 mod threads;
@@ -1,13 +1,399 @@
+#![allow(dead_code)]
+
+#[cfg(test)]
+#[macro_use]
+mod diff;
+mod fixed_width;
+mod timestamp;
+mod xml;
+
+use std::{fmt, io};
+
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+/// What to do with input fields the target type doesn't declare
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+enum UnknownFields {
+    /// Fail the load, naming every unknown field
+    Deny,
+    /// Drop them (serde's default)
+    #[default]
+    Ignore,
+    /// Keep them in `WithExtras::extras` so they can be written back out
+    Capture,
+}
+
+/// A type's own unknown-field policy, used by `Loader::for_type`
+trait Record: DeserializeOwned {
+    const UNKNOWN_FIELDS: UnknownFields = UnknownFields::Ignore;
+}
+
+/// A loaded value plus any unknown fields captured alongside it.
+/// Serializes back to the original shape: known fields then extras.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct WithExtras<T> {
+    #[serde(flatten)]
+    value: T,
+    #[serde(flatten)]
+    extras: Map<String, Value>,
+}
+
+#[derive(Debug)]
+enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    UnknownFields(Vec<String>),
+    /// Deny or Capture for a type whose fields can't be listed, e.g. one with flattened fields
+    UnlistedFields(&'static str),
+    /// Required fields no header matched, with the headers that were there
+    MissingFields { fields: Vec<String>, headers: Vec<String> },
+    /// More than one header matched the same field
+    AmbiguousHeaders { field: String, headers: Vec<String> },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::Json(e) => e.fmt(f),
+            Error::Csv(e) => e.fmt(f),
+            Error::UnknownFields(fields) => write!(f, "unknown field(s): {}", fields.join(", ")),
+            Error::UnlistedFields(ty) => write!(f, "can't list the fields of {ty} to find unknown ones"),
+            Error::MissingFields { fields, headers } => write!(f,
+                "missing field(s): {}; headers seen: {}", fields.join(", "), headers.join(", ")
+            ),
+            Error::AmbiguousHeaders { field, headers } => write!(f,
+                "field {} matched by more than one header: {}", field, headers.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::Csv(e) => Some(e),
+            Error::UnknownFields(_)
+            | Error::UnlistedFields(_)
+            | Error::MissingFields { .. }
+            | Error::AmbiguousHeaders { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        Error::Csv(e)
+    }
+}
+
+// So tests returning io::Result can still use ?
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Field names a derived `Deserialize` expects, found by handing it a deserializer that
+/// records the names it's given and then bails out. None if `T` isn't deserialized as a
+/// plain struct, e.g. because it has `#[serde(flatten)]` fields.
+fn field_names<T: DeserializeOwned>() -> Option<&'static [&'static str]> {
+    struct Probe<'a>(&'a mut Option<&'static [&'static str]>);
+
+    impl<'de> Deserializer<'de> for Probe<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = Some(fields);
+            Err(de::Error::custom("probed"))
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields = None;
+    let _ = T::deserialize(Probe(&mut fields));
+    fields
+}
+
+/// Folds case, and treats runs of whitespace, `_` and `-` as one separator:
+/// `FULL NAME`, `Full_Name` and ` full-name ` all become `full_name`
+fn fold_header(s: &str) -> String {
+    s.split(|c: char| c.is_whitespace() || c == '_' || c == '-')
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+#[derive(Debug, Clone)]
+struct FieldAliases {
+    field: String,
+    // Folded, including the field name itself
+    aliases: Vec<String>,
+    required: bool,
+}
+
+/// Renames CSV headers to field names before deserializing.
+/// Headers for fields not listed here pass through unchanged.
+#[derive(Debug, Clone, Default)]
+struct HeaderAliases {
+    fields: Vec<FieldAliases>,
+}
+
+/// Which header was taken for a field
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HeaderMatch {
+    field: String,
+    header: String,
+    column: usize,
+}
+
+impl HeaderAliases {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// A required field and its alternative names. The field's own name always matches.
+    fn field(self, field: &str, aliases: &[&str]) -> Self {
+        self.add(field, aliases, true)
+    }
+
+    /// As `field` but no error if nothing matches
+    fn optional(self, field: &str, aliases: &[&str]) -> Self {
+        self.add(field, aliases, false)
+    }
+
+    fn add(mut self, field: &str, aliases: &[&str], required: bool) -> Self {
+        let aliases = std::iter::once(field).chain(aliases.iter().copied()).map(fold_header).collect();
+        self.fields.push(FieldAliases { field: field.to_owned(), aliases, required });
+        self
+    }
+
+    fn resolve(&self, headers: &csv::StringRecord) -> Result<(csv::StringRecord, Vec<HeaderMatch>), Error> {
+        let mut resolved: Vec<&str> = headers.iter().collect();
+        let mut matches: Vec<HeaderMatch> = vec![];
+
+        for (column, header) in headers.iter().enumerate() {
+            let folded = fold_header(header);
+            let Some(f) = self.fields.iter().find(|f| f.aliases.contains(&folded)) else {
+                continue;
+            };
+
+            if let Some(prev) = matches.iter().find(|m| m.field == f.field) {
+                return Err(Error::AmbiguousHeaders {
+                    field: f.field.clone(),
+                    headers: vec![prev.header.clone(), header.to_owned()],
+                });
+            }
+            resolved[column] = &f.field;
+            matches.push(HeaderMatch { field: f.field.clone(), header: header.to_owned(), column });
+        }
+
+        let missing: Vec<String> = self.fields.iter()
+            .filter(|f| f.required && !matches.iter().any(|m| m.field == f.field))
+            .map(|f| f.field.clone())
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::MissingFields {
+                fields: missing,
+                headers: headers.iter().map(str::to_owned).collect(),
+            });
+        }
+
+        Ok((csv::StringRecord::from(resolved), matches))
+    }
+}
+
+/// Reads JSON documents and CSV files, applying the same unknown-field policy to both
+#[derive(Debug, Clone, Default)]
+struct Loader {
+    unknown_fields: UnknownFields,
+    header_aliases: Option<HeaderAliases>,
+}
+
+impl Loader {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn for_type<T: Record>() -> Self {
+        Self { unknown_fields: T::UNKNOWN_FIELDS, ..Self::default() }
+    }
+
+    fn unknown_fields(mut self, policy: UnknownFields) -> Self {
+        self.unknown_fields = policy;
+        self
+    }
+
+    /// CSV only: JSON keys are left to `#[serde(alias)]`
+    fn header_aliases(mut self, aliases: HeaderAliases) -> Self {
+        self.header_aliases = Some(aliases);
+        self
+    }
+
+    /// The fields of `T`, or None if the policy is Ignore and there's no need to know them
+    fn known_fields<T: DeserializeOwned>(&self) -> Result<Option<&'static [&'static str]>, Error> {
+        match self.unknown_fields {
+            UnknownFields::Ignore => Ok(None),
+            UnknownFields::Deny | UnknownFields::Capture => field_names::<T>()
+                .map(Some)
+                .ok_or(Error::UnlistedFields(std::any::type_name::<T>())),
+        }
+    }
+
+    fn check_unknown<'a>(&self, unknown: impl IntoIterator<Item = &'a str>) -> Result<(), Error> {
+        if self.unknown_fields == UnknownFields::Deny {
+            let unknown: Vec<_> = unknown.into_iter().map(str::to_owned).collect();
+            if !unknown.is_empty() {
+                return Err(Error::UnknownFields(unknown));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_json<T: DeserializeOwned>(&self, rdr: impl io::Read) -> Result<WithExtras<T>, Error> {
+        let value = serde_json::from_reader(rdr)?;
+        self.read_json_value(value)
+    }
+
+    fn read_json_value<T: DeserializeOwned>(&self, value: Value) -> Result<WithExtras<T>, Error> {
+        let known = self.known_fields::<T>()?;
+        let mut extras = Map::new();
+        let value = match (value, known) {
+            (Value::Object(mut map), Some(known)) => {
+                let unknown: Vec<String> = map.keys()
+                    .filter(|k| !known.contains(&k.as_str()))
+                    .cloned()
+                    .collect();
+                self.check_unknown(unknown.iter().map(String::as_str))?;
+                if self.unknown_fields == UnknownFields::Capture {
+                    for k in unknown {
+                        let v = map.remove(&k).expect("key was just listed");
+                        extras.insert(k, v);
+                    }
+                }
+                Value::Object(map)
+            }
+            (value, _) => value,
+        };
+
+        Ok(WithExtras { value: T::deserialize(value)?, extras })
+    }
+
+    fn read_csv<T: DeserializeOwned>(&self, rdr: impl io::Read) -> Result<Vec<WithExtras<T>>, Error> {
+        self.read_csv_with_report(rdr).map(|(records, _)| records)
+    }
+
+    /// As `read_csv`, also reporting which header was matched to each aliased field
+    fn read_csv_with_report<T: DeserializeOwned>(&self, rdr: impl io::Read)
+        -> Result<(Vec<WithExtras<T>>, Vec<HeaderMatch>), Error>
+    {
+        let mut rdr = csv::Reader::from_reader(rdr);
+        let (headers, matches) = match &self.header_aliases {
+            Some(aliases) => aliases.resolve(rdr.headers()?)?,
+            None => (rdr.headers()?.clone(), vec![]),
+        };
+
+        let unknown: Vec<usize> = match self.known_fields::<T>()? {
+            Some(known) => (0..headers.len()).filter(|&i| !known.contains(&&headers[i])).collect(),
+            None => vec![],
+        };
+        self.check_unknown(unknown.iter().map(|&i| &headers[i]))?;
+        let capture = self.unknown_fields == UnknownFields::Capture;
+
+        let records = rdr.records()
+            .map(|record| {
+                let record = record?;
+                let value = record.deserialize(Some(&headers))?;
+                let extras = unknown.iter()
+                    .filter(|_| capture)
+                    .filter_map(|&i| Some((headers[i].to_owned(), Value::from(record.get(i)?))))
+                    .collect();
+                Ok(WithExtras { value, extras })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok((records, matches))
+    }
+}
+
+/// Writes records as CSV with their captured extras as trailing columns.
+/// The extra columns are those of the first record; later records leave any missing ones empty.
+fn write_csv<T: Serialize>(wtr: impl io::Write, records: &[WithExtras<T>]) -> Result<(), Error> {
+    let mut wtr = csv::Writer::from_writer(wtr);
+    let extra_keys: Vec<&String> = records.first().map(|r| r.extras.keys().collect()).unwrap_or_default();
+
+    for (i, record) in records.iter().enumerate() {
+        // Let csv flatten the known fields, then read them back to append the extras
+        let mut buf = csv::Writer::from_writer(vec![]);
+        buf.serialize(&record.value)?;
+        let buf = buf.into_inner().map_err(|e| e.into_error())?;
+        let mut rows = csv::Reader::from_reader(&buf[..]);
+
+        if i == 0 {
+            let mut headers = rows.headers()?.clone();
+            headers.extend(extra_keys.iter().map(|k| k.as_str()));
+            wtr.write_record(&headers)?;
+        }
+        for row in rows.records() {
+            let mut row = row?;
+            for k in &extra_keys {
+                match record.extras.get(*k) {
+                    Some(Value::String(s)) => row.push_field(s),
+                    Some(v) => row.push_field(&v.to_string()),
+                    None => row.push_field(""),
+                }
+            }
+            wtr.write_record(&row)?;
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use serde::Deserialize;
     use std::fs::File;
     use std::io;
     use std::path::PathBuf;
     use std::sync::LazyLock;
 
-    use serde::{Deserialize, Serialize};
-
     static DATA: LazyLock<PathBuf, fn() -> PathBuf> =
         LazyLock::new(|| std::env::current_dir().unwrap().join("data"));
 
@@ -43,7 +429,7 @@ mod tests {
             age: 43,
             phones: vec!["+44 1234567".to_string(), "+44 2345678".to_string()]
         };
-        assert_eq!(p, john);
+        assert_same!(p, john);
         Ok(())
     }
 
@@ -89,7 +475,7 @@ mod tests {
             age: 43,
             phones: vec!["+44 1234567".to_string(), "+44 2345678".to_string()]
         };
-        assert_eq!(v, [john]);
+        assert_same!(v, [john]);
         Ok(())
     }
 
@@ -109,4 +495,186 @@ mod tests {
         assert_eq!(v, [john]);
         Ok(())
     }
+
+    #[test]
+    fn json_unknown_ignore() -> io::Result<()> {
+        let f = File::open(DATA.join("file.json"))?;
+
+        let p: WithExtras<PersonMini> = Loader::new().read_json(f)?;
+        assert_eq!(p.value, PersonMini { name: "John Doe".to_string(), age: 43 });
+        assert!(p.extras.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn json_unknown_deny() -> io::Result<()> {
+        let f = File::open(DATA.join("file.json"))?;
+
+        let result = Loader::new()
+            .unknown_fields(UnknownFields::Deny)
+            .read_json::<PersonMini>(f);
+        assert!(matches!(result, Err(Error::UnknownFields(fields)) if fields == ["address", "phones"]));
+        Ok(())
+    }
+
+    #[test]
+    fn json_unknown_capture_round_trip() -> io::Result<()> {
+        let original: Value = serde_json::from_reader(File::open(DATA.join("file.json"))?)?;
+
+        let p: WithExtras<PersonMini> = Loader::new()
+            .unknown_fields(UnknownFields::Capture)
+            .read_json_value(original.clone())?;
+        assert_eq!(p.value, PersonMini { name: "John Doe".to_string(), age: 43 });
+        assert_eq!(p.extras.keys().collect::<Vec<_>>(), ["address", "phones"]);
+
+        // Nothing lost
+        assert_eq!(serde_json::to_value(&p)?, original);
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq, Eq, Deserialize)]
+    struct PersonStrict {
+        name: String,
+        age: u8,
+    }
+
+    impl Record for PersonStrict {
+        const UNKNOWN_FIELDS: UnknownFields = UnknownFields::Deny;
+    }
+
+    #[test]
+    fn unknown_per_type() -> io::Result<()> {
+        let result = Loader::for_type::<PersonStrict>()
+            .read_json::<PersonStrict>(File::open(DATA.join("file.json"))?);
+        assert!(matches!(result, Err(Error::UnknownFields(_))));
+
+        // Per-load setting overrides the type's
+        let p = Loader::for_type::<PersonStrict>()
+            .unknown_fields(UnknownFields::Ignore)
+            .read_json::<PersonStrict>(File::open(DATA.join("file.json"))?)?;
+        assert_eq!(p.value, PersonStrict { name: "John Doe".to_string(), age: 43 });
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq, Eq, Deserialize)]
+    struct PersonFlat {
+        #[serde(flatten)]
+        person: PersonMini,
+    }
+
+    #[test]
+    fn unknown_unlisted() -> io::Result<()> {
+        // Flattening hides the field names, so unknown ones can't be told apart
+        for policy in [UnknownFields::Deny, UnknownFields::Capture] {
+            let loader = Loader::new().unknown_fields(policy);
+            let result = loader.read_json::<PersonFlat>(File::open(DATA.join("file.json"))?);
+            assert!(matches!(result, Err(Error::UnlistedFields(ty)) if ty.ends_with("PersonFlat")));
+            let result = loader.read_csv::<PersonFlat>(File::open(DATA.join("file.csv"))?);
+            assert!(matches!(result, Err(Error::UnlistedFields(_))));
+        }
+
+        let p = Loader::new().read_json::<PersonFlat>(File::open(DATA.join("file.json"))?)?;
+        assert_eq!(p.value.person, PersonMini { name: "John Doe".to_string(), age: 43 });
+        Ok(())
+    }
+
+    #[test]
+    fn csv_unknown_deny() -> io::Result<()> {
+        let f = File::open(DATA.join("file.csv"))?;
+
+        let result = Loader::new()
+            .unknown_fields(UnknownFields::Deny)
+            .read_csv::<Person>(f);
+        assert!(matches!(result, Err(Error::UnknownFields(fields)) if fields == ["street", "city"]));
+        Ok(())
+    }
+
+    #[test]
+    fn csv_unknown_capture_round_trip() -> io::Result<()> {
+        let f = File::open(DATA.join("file.csv"))?;
+        let loader = Loader::new().unknown_fields(UnknownFields::Capture);
+
+        let v: Vec<WithExtras<PersonMini>> = loader.read_csv(f)?;
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].value, PersonMini { name: "John Doe".to_string(), age: 43 });
+        assert_eq!(v[0].extras["street"], "10 Downing Street");
+        assert_eq!(v[0].extras["city"], "London");
+        assert_eq!(v[0].extras["phones"], "+44 1234567,+44 2345678");
+
+        let mut buf = vec![];
+        write_csv(&mut buf, &v)?;
+        let v2: Vec<WithExtras<PersonMini>> = loader.read_csv(&buf[..])?;
+        assert_eq!(v2, v);
+        Ok(())
+    }
+
+    #[test]
+    fn fold_headers() {
+        assert_eq!(fold_header("name"), "name");
+        assert_eq!(fold_header("FULL NAME"), "full_name");
+        assert_eq!(fold_header(" Full_Name "), "full_name");
+        assert_eq!(fold_header("full -\tname"), "full_name");
+        assert_eq!(fold_header("ÉCOLE"), "école");
+    }
+
+    fn person_aliases() -> HeaderAliases {
+        HeaderAliases::new()
+            .field("name", &["full_name"])
+            .field("age", &["years"])
+            .optional("phones", &["phone", "telephone"])
+    }
+
+    #[test]
+    fn csv_header_aliases() -> io::Result<()> {
+        let f = File::open(DATA.join("file-aliased.csv"))?;
+
+        let (v, matches) = Loader::new()
+            .header_aliases(person_aliases())
+            .read_csv_with_report::<PersonMini>(f)?;
+        assert_eq!(v.len(), 1);
+        assert_eq!(v[0].value, PersonMini { name: "John Doe".to_string(), age: 43 });
+
+        let matches: Vec<_> = matches.iter()
+            .map(|m| (m.field.as_str(), m.header.as_str(), m.column))
+            .collect();
+        assert_eq!(matches, [("name", "FULL NAME", 0), ("age", " Age ", 1), ("phones", "Telephone", 4)]);
+        Ok(())
+    }
+
+    #[test]
+    fn csv_header_aliases_unknown() -> io::Result<()> {
+        let f = File::open(DATA.join("file-aliased.csv"))?;
+
+        // Unknown fields are judged after renaming
+        let result = Loader::new()
+            .header_aliases(person_aliases())
+            .unknown_fields(UnknownFields::Deny)
+            .read_csv::<Person>(f);
+        assert!(matches!(result, Err(Error::UnknownFields(fields)) if fields == ["Street", "City"]));
+        Ok(())
+    }
+
+    #[test]
+    fn csv_header_aliases_missing() -> io::Result<()> {
+        let f = File::open(DATA.join("file-aliased.csv"))?;
+
+        let aliases = HeaderAliases::new()
+            .field("name", &[])
+            .field("age", &[]);
+        let result = Loader::new()
+            .header_aliases(aliases)
+            .read_csv::<PersonMini>(f);
+        let Err(e) = result else { panic!("Expected an error") };
+        assert_eq!(e.to_string(),
+            "missing field(s): name; headers seen: FULL NAME,  Age , Street, City, Telephone");
+        Ok(())
+    }
+
+    #[test]
+    fn csv_header_aliases_ambiguous() {
+        let headers = csv::StringRecord::from(vec!["Name", "full_name", "age"]);
+
+        let result = person_aliases().resolve(&headers);
+        assert!(matches!(result, Err(Error::AmbiguousHeaders { field, .. }) if field == "name"));
+    }
 }
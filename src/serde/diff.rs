@@ -0,0 +1,197 @@
+/*
+ * Field-level comparison of any two serializable values, for readable test failures.
+ *
+ * Both sides go through serde_json::Value and are walked together, so only the paths that
+ * differ are reported, e.g. `address.city` or `phones[1]`, instead of one long Debug dump.
+ */
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Difference {
+    path: String,
+    /// None if the path is missing on this side
+    expected: Option<Value>,
+    actual: Option<Value>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn side(v: &Option<Value>) -> String {
+            v.as_ref().map_or_else(|| "<missing>".to_owned(), Value::to_string)
+        }
+
+        let path = if self.path.is_empty() { "(root)" } else { &self.path };
+        write!(f, "{}: expected {}, actual {}", path, side(&self.expected), side(&self.actual))
+    }
+}
+
+/// Every path where the serialized forms differ. An `ignore` entry matches either a
+/// whole path (`address.city`) or a field name at any depth (`updated`).
+fn diff<E, A>(expected: &E, actual: &A, ignore: &[&str]) -> Result<Vec<Difference>, serde_json::Error>
+where
+    E: Serialize + ?Sized,
+    A: Serialize + ?Sized,
+{
+    let mut diffs = vec![];
+    walk(
+        String::new(),
+        Some(&serde_json::to_value(expected)?),
+        Some(&serde_json::to_value(actual)?),
+        ignore,
+        &mut diffs,
+    );
+    Ok(diffs)
+}
+
+fn walk(path: String, expected: Option<&Value>, actual: Option<&Value>, ignore: &[&str], diffs: &mut Vec<Difference>) {
+    match (expected, actual) {
+        (Some(Value::Object(e)), Some(Value::Object(a))) => {
+            // Expected's keys (sorted, since Map is a BTreeMap), then any only in actual
+            let keys = e.keys().chain(a.keys().filter(|k| !e.contains_key(*k)));
+            for k in keys {
+                if ignore.contains(&k.as_str()) {
+                    continue;
+                }
+                let child = if path.is_empty() { k.clone() } else { format!("{path}.{k}") };
+                if !ignore.contains(&child.as_str()) {
+                    walk(child, e.get(k), a.get(k), ignore, diffs);
+                }
+            }
+        }
+        (Some(Value::Array(e)), Some(Value::Array(a))) => {
+            for i in 0..e.len().max(a.len()) {
+                let child = format!("{path}[{i}]");
+                if !ignore.contains(&child.as_str()) {
+                    walk(child, e.get(i), a.get(i), ignore, diffs);
+                }
+            }
+        }
+        (e, a) if e != a => diffs.push(Difference { path, expected: e.cloned(), actual: a.cloned() }),
+        _ => {}
+    }
+}
+
+/// The panic message for `assert_same!`, or Ok if there are no differences
+pub(crate) fn check<E, A>(expected: &E, actual: &A, ignore: &[&str]) -> Result<(), String>
+where
+    E: Serialize + ?Sized,
+    A: Serialize + ?Sized,
+{
+    let diffs = diff(expected, actual, ignore).map_err(|e| format!("can't compare values: {e}"))?;
+    if diffs.is_empty() {
+        return Ok(());
+    }
+
+    let mut msg = format!("values differ at {} path(s):", diffs.len());
+    for d in diffs {
+        msg.push_str("\n  ");
+        msg.push_str(&d.to_string());
+    }
+    Err(msg)
+}
+
+/// Like `assert_eq!(actual, expected)` but compares serialized forms and reports only the
+/// differing paths. Optionally `ignore = ["field", "a.path"]`.
+macro_rules! assert_same {
+    ($actual:expr, $expected:expr $(,)?) => {
+        assert_same!($actual, $expected, ignore = [])
+    };
+    ($actual:expr, $expected:expr, ignore = [$($ignore:expr),* $(,)?] $(,)?) => {
+        match (&$actual, &$expected) {
+            (actual, expected) => {
+                if let ::std::result::Result::Err(msg) =
+                    $crate::serde::diff::check(expected, actual, &[$($ignore),*]) {
+                    ::std::panic!("{}", msg);
+                }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Serialize)]
+    struct Address {
+        street: String,
+        city: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct Person {
+        name: String,
+        age: u8,
+        address: Address,
+        phones: Vec<String>,
+        updated: u64,
+    }
+
+    fn john() -> Person {
+        Person {
+            name: "John Doe".to_string(),
+            age: 43,
+            address: Address { street: "10 Downing Street".to_string(), city: "London".to_string() },
+            phones: vec!["+44 1234567".to_string(), "+44 2345678".to_string()],
+            updated: 1698013773,
+        }
+    }
+
+    #[test]
+    fn no_differences() -> Result<(), serde_json::Error> {
+        assert_eq!(diff(&john(), &john(), &[])?, []);
+        assert_same!(john(), john());
+        Ok(())
+    }
+
+    #[test]
+    fn differences() -> Result<(), serde_json::Error> {
+        let mut p = john();
+        p.age = 44;
+        p.address.city = "Paris".to_string();
+        p.phones.pop();
+
+        let diffs = diff(&john(), &p, &[])?;
+        assert_eq!(diffs, [
+            Difference { path: "address.city".to_string(), expected: Some(json!("London")), actual: Some(json!("Paris")) },
+            Difference { path: "age".to_string(), expected: Some(json!(43)), actual: Some(json!(44)) },
+            Difference { path: "phones[1]".to_string(), expected: Some(json!("+44 2345678")), actual: None },
+        ]);
+        assert_eq!(diffs[2].to_string(), r#"phones[1]: expected "+44 2345678", actual <missing>"#);
+        Ok(())
+    }
+
+    #[test]
+    fn ignore() -> Result<(), serde_json::Error> {
+        let mut p = john();
+        p.updated += 60;
+        p.address.street = "11 Downing Street".to_string();
+
+        assert_eq!(diff(&john(), &p, &["updated"])?.len(), 1);
+        assert_same!(p, john(), ignore = ["updated", "address.street"]);
+        Ok(())
+    }
+
+    #[test]
+    fn different_types() -> Result<(), serde_json::Error> {
+        // Compares shape, not Rust type
+        let expected = json!({ "name": "John Doe", "age": 43, "phones": ["+44 1234567", "+44 2345678"] });
+        assert_same!(john(), expected, ignore = ["address", "updated"]);
+
+        let diffs = diff(&json!({ "a": 1 }), &json!({ "b": 1 }), &[])?;
+        assert_eq!(diffs.iter().map(|d| d.path.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "values differ at 1 path(s):\n  address.city: expected \"London\", actual \"Paris\"")]
+    fn assert_same_fails() {
+        let mut p = john();
+        p.address.city = "Paris".to_string();
+        assert_same!(p, john());
+    }
+}
@@ -0,0 +1,573 @@
+/*
+ * Fixed-width ("mainframe") text records via serde.
+ *
+ * Each field has a start column and width, declared with `#[column(...)]` inside the
+ * `fixed_width!` macro. Padding follows the std::fmt rules exercised in fmt.rs:
+ * Left is `{:<w}`, Right is `{:>w}` and ZeroPad is numeric `{:0w}` (sign first, then zeros).
+ * Positions and widths count chars, after decoding, not bytes.
+ *
+ * Columns are matched to fields by their Rust names, so serde renames aren't supported:
+ * writing a record whose field was renamed is an error rather than a blank column.
+ * Empty ZeroPad values (None, "") are written as spaces, so they can be told from zero.
+ *
+ * Reading strips the fill, not knowing how much of it was padding, so fill chars are only
+ * safe where values can't start (Right) or end (Left) with them. ZeroPad's zeros are only
+ * stripped from numbers: a ZeroPad string keeps any that were added, so "0012" stays "0012"
+ * but "12" padded to "0012" does too.
+ */
+use std::fmt;
+use std::io::{self, BufRead, BufReader};
+
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Align {
+    /// `{:<w}`: value then fill
+    Left,
+    /// `{:>w}`: fill then value
+    Right,
+    /// `{:0w}`: sign, zeros, digits. Non-numbers are right-aligned with zeros, as `{:0>w}`.
+    ZeroPad,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Column {
+    name: &'static str,
+    start: usize,
+    width: usize,
+    align: Align,
+    fill: char,
+}
+
+impl Column {
+    const fn new(name: &'static str, start: usize, width: usize) -> Self {
+        Self { name, start, width, align: Align::Left, fill: ' ' }
+    }
+
+    const fn align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    const fn fill(mut self, fill: char) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    fn pad(&self, text: &str, numeric: bool) -> String {
+        let (w, fill) = (self.width, self.fill);
+        match self.align {
+            Align::Left => text.to_owned() + &pad_fill(text, w, fill),
+            Align::Right => pad_fill(text, w, fill) + text,
+            Align::ZeroPad if text.is_empty() => " ".repeat(w),
+            Align::ZeroPad if numeric => match text.strip_prefix('-') {
+                Some(digits) => format!("-{:0>1$}", digits, w - 1),
+                None => format!("{text:0>w$}"),
+            },
+            Align::ZeroPad => format!("{text:0>w$}"),
+        }
+    }
+
+    /// Strips the fill. ZeroPad zeros are left, as a string's may be its own.
+    fn unpad<'a>(&self, text: &'a str) -> &'a str {
+        match self.align {
+            Align::Left => text.trim_end_matches(self.fill),
+            Align::Right => text.trim_start_matches(self.fill),
+            Align::ZeroPad => text,
+        }
+    }
+}
+
+// The fill the std::fmt width would add, for any fill char
+fn pad_fill(text: &str, width: usize, fill: char) -> String {
+    let n = width.saturating_sub(text.chars().count());
+    std::iter::repeat_n(fill, n).collect()
+}
+
+trait FixedWidth {
+    const COLUMNS: &'static [Column];
+
+    fn line_width() -> usize {
+        Self::COLUMNS.iter().map(|c| c.start + c.width).max().unwrap_or(0)
+    }
+}
+
+/// Declares a struct with a `#[column(start = _, width = _)]` on every field and
+/// implements `FixedWidth` for it. Optional `align = Left | Right | ZeroPad` and
+/// `fill = 'c'` follow the width. Other attributes, like derives, pass through.
+#[allow(unused_macros)]
+macro_rules! fixed_width {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                #[column(start = $start:expr, width = $width:expr
+                    $(, align = $align:ident)? $(, fill = $fill:literal)?)]
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident: $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl FixedWidth for $name {
+            const COLUMNS: &'static [Column] = &[
+                $(
+                    Column::new(stringify!($field), $start, $width)
+                        $(.align(Align::$align))?
+                        $(.fill($fill))?
+                ),*
+            ];
+        }
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Overflow {
+    field: &'static str,
+    width: usize,
+    value: String,
+}
+
+#[derive(Debug)]
+enum Error {
+    Io(io::Error),
+    /// Every field too wide for its column, not just the first
+    Overflow(Vec<Overflow>),
+    /// Line number (from 1) of a line with characters the encoding can't represent
+    Unencodable(usize),
+    Message(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::Overflow(overflows) => {
+                write!(f, "field(s) too wide:")?;
+                for o in overflows {
+                    write!(f, " {} {:?} exceeds {}", o.field, o.value, o.width)?;
+                }
+                Ok(())
+            }
+            Error::Unencodable(line) => write!(f, "line {line} can't be encoded"),
+            Error::Message(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Message(e.to_string())
+    }
+}
+
+/// One record to one line, without line ending
+fn to_string<T: Serialize + FixedWidth>(value: &T) -> Result<String, Error> {
+    // serde_json's Value is a convenient self-describing intermediate for flat records
+    let Value::Object(fields) = serde_json::to_value(value)? else {
+        return Err(de::Error::custom("fixed-width records must be structs"));
+    };
+
+    let mut line: Vec<char> = vec![' '; T::line_width()];
+    let mut overflows = vec![];
+    for column in T::COLUMNS {
+        let (text, numeric) = match fields.get(column.name) {
+            None => return Err(de::Error::custom(format!("no field {} (renamed?)", column.name))),
+            Some(Value::Null) => (String::new(), false),
+            Some(Value::String(s)) => (s.clone(), false),
+            Some(Value::Number(n)) => (n.to_string(), true),
+            Some(Value::Bool(b)) => (if *b { "Y" } else { "N" }.to_owned(), false),
+            Some(v) => return Err(de::Error::custom(format!("{} isn't a scalar: {v}", column.name))),
+        };
+
+        if text.chars().count() > column.width {
+            overflows.push(Overflow { field: column.name, width: column.width, value: text });
+            continue;
+        }
+        let padded = column.pad(&text, numeric);
+        line.splice(column.start..column.start + column.width, padded.chars());
+    }
+
+    if !overflows.is_empty() {
+        return Err(Error::Overflow(overflows));
+    }
+    Ok(line.into_iter().collect())
+}
+
+/// One line to one record. Short lines are treated as padded with spaces.
+fn from_str<T: DeserializeOwned + FixedWidth>(line: &str) -> Result<T, Error> {
+    let chars: Vec<char> = line.chars().collect();
+    let fields = T::COLUMNS.iter().map(|column| {
+        let start = column.start.min(chars.len());
+        let end = (column.start + column.width).min(chars.len());
+        let text: String = chars[start..end].iter().collect();
+        let text = text.trim_end_matches(' ');
+        let field = FieldDeserializer {
+            name: column.name,
+            text: column.unpad(text).to_owned(),
+            number: unpad_number(column, text),
+        };
+        (column.name, field)
+    });
+
+    T::deserialize(de::value::MapDeserializer::new(fields))
+}
+
+/// The field as a number: without the fill, or a ZeroPad's zeros
+fn unpad_number(column: &Column, text: &str) -> String {
+    match column.align {
+        // Blank, so empty rather than a zero
+        Align::ZeroPad if text.is_empty() => String::new(),
+        // The sign's zeros, or all but the last of a zero
+        Align::ZeroPad => match text.strip_prefix('-') {
+            Some(rest) => format!("-{}", rest.trim_start_matches('0')),
+            None => match text.trim_start_matches('0') {
+                rest if rest.is_empty() || rest.starts_with('.') => format!("0{rest}"),
+                rest => rest.to_owned(),
+            },
+        },
+        _ => column.unpad(text).to_owned(),
+    }
+}
+
+/// Decodes `encoding` and yields one record per line
+fn read_records<T, R>(rdr: R, encoding: &'static Encoding) -> impl Iterator<Item = Result<T, Error>>
+where
+    T: DeserializeOwned + FixedWidth,
+    R: io::Read,
+{
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .bom_sniffing(false)
+        .build(rdr);
+
+    BufReader::new(decoder).lines().map(|line| from_str(&line?))
+}
+
+/// Writes one record per line in `encoding`, with CRLF line endings as is usual for the format
+fn write_records<'a, T, W>(mut wtr: W, records: impl IntoIterator<Item = &'a T>, encoding: &'static Encoding) -> Result<(), Error>
+where
+    T: Serialize + FixedWidth + 'a,
+    W: io::Write,
+{
+    for (i, record) in records.into_iter().enumerate() {
+        let line = to_string(record)? + "\r\n";
+        let (bytes, _, had_errors) = encoding.encode(&line);
+        if had_errors {
+            return Err(Error::Unencodable(i + 1));
+        }
+        wtr.write_all(&bytes)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Parses an unpadded field as whatever type the target asks for
+struct FieldDeserializer {
+    name: &'static str,
+    text: String,
+    /// As `text` but also without ZeroPad zeros
+    number: String,
+}
+
+impl<'de> IntoDeserializer<'de, Error> for FieldDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl FieldDeserializer {
+    fn parse<T>(&self) -> Result<T, Error>
+    where
+        T: std::str::FromStr,
+        T::Err: fmt::Display,
+    {
+        self.number.parse().map_err(|e| de::Error::custom(
+            format!("invalid {} {:?}: {}", self.name, self.text, e)
+        ))
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for FieldDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.text)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.text.as_str() {
+            "Y" => visitor.visit_bool(true),
+            "N" => visitor.visit_bool(false),
+            _ => Err(de::Error::custom(format!("invalid {} {:?}: expected Y or N", self.name, self.text))),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut chars = self.text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(de::Error::custom(format!("invalid {} {:?}: expected one char", self.name, self.text))),
+        }
+    }
+
+    // Blank means None
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.text.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_enum(self.text.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::sync::LazyLock;
+
+    use encoding_rs::WINDOWS_1252;
+    use serde::Deserialize;
+
+    static DATA: LazyLock<PathBuf, fn() -> PathBuf> =
+        LazyLock::new(|| std::env::current_dir().unwrap().join("data"));
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "UPPERCASE")]
+    enum Currency {
+        Gbp,
+        Eur,
+    }
+
+    fixed_width! {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Payment {
+            #[column(start = 0, width = 16)]
+            name: String,
+            #[column(start = 16, width = 3, align = ZeroPad)]
+            age: u8,
+            #[column(start = 19, width = 10, align = Right)]
+            amount: f64,
+            #[column(start = 30, width = 3)]
+            currency: Currency,
+            #[column(start = 33, width = 6, align = Right, fill = '*')]
+            reference: Option<String>,
+        }
+    }
+
+    fn john() -> Payment {
+        Payment {
+            name: "John Doe".to_string(),
+            age: 43,
+            amount: -1234.5,
+            currency: Currency::Gbp,
+            reference: Some("AB12".to_string()),
+        }
+    }
+
+    #[test]
+    fn write_line() -> Result<(), Error> {
+        assert_eq!(to_string(&john())?, "John Doe        043   -1234.5 GBP**AB12");
+
+        let anon = Payment { reference: None, ..john() };
+        assert_eq!(to_string(&anon)?, "John Doe        043   -1234.5 GBP******");
+        Ok(())
+    }
+
+    #[test]
+    fn read_line() -> Result<(), Error> {
+        let p: Payment = from_str("John Doe        043   -1234.5 GBP**AB12")?;
+        assert_eq!(p, john());
+
+        // Trailing spaces trimmed by an editor: blank reference
+        let p: Payment = from_str("John Doe        043   -1234.5 GBP")?;
+        assert_eq!(p, Payment { reference: None, ..john() });
+        Ok(())
+    }
+
+    #[test]
+    fn zero_pad() {
+        let column = Column::new("n", 0, 5).align(Align::ZeroPad);
+        // As format!("{:05}", n)
+        assert_eq!(column.pad("123", true), "00123");
+        assert_eq!(column.pad("-123", true), "-0123");
+        assert_eq!(unpad_number(&column, "00123"), "123");
+        assert_eq!(unpad_number(&column, "-0123"), "-123");
+        assert_eq!(unpad_number(&column, "00000"), "0");
+        assert_eq!(unpad_number(&column, "000.5"), "0.5");
+        // Strings keep their zeros
+        assert_eq!(column.unpad("00123"), "00123");
+        // Empty is blank, not zero
+        assert_eq!(column.pad("", false), "     ");
+        assert_eq!(unpad_number(&column, ""), "");
+    }
+
+    fixed_width! {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Code {
+            #[column(start = 0, width = 4, align = ZeroPad)]
+            code: Option<String>,
+            #[column(start = 4, width = 3, align = ZeroPad)]
+            count: Option<u16>,
+        }
+    }
+
+    #[test]
+    fn zero_pad_empty() -> Result<(), Error> {
+        for code in [
+            Code { code: None, count: None },
+            Code { code: Some("".to_string()), count: Some(0) },
+            Code { code: Some("0012".to_string()), count: Some(12) },
+        ] {
+            let line = to_string(&code)?;
+            let back: Code = from_str(&line)?;
+            // Some("") and None are both blank
+            let expected = Code { code: code.code.clone().filter(|c| !c.is_empty()), ..code };
+            assert_eq!(back, expected, "{line:?}");
+        }
+        assert_eq!(to_string(&Code { code: None, count: Some(0) })?, "    000");
+
+        // A string can't tell its own zeros from padding
+        let back: Code = from_str(&to_string(&Code { code: Some("12".to_string()), count: None })?)?;
+        assert_eq!(back.code.as_deref(), Some("0012"));
+        Ok(())
+    }
+
+    fixed_width! {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Renamed {
+            #[column(start = 0, width = 6)]
+            name: String,
+        }
+    }
+
+    #[test]
+    fn renamed() {
+        // Not written as blanks
+        let Err(Error::Message(msg)) = to_string(&Renamed { name: "Bob".to_string() }) else {
+            panic!("Expected an error");
+        };
+        assert_eq!(msg, "no field name (renamed?)");
+        assert!(from_str::<Renamed>("Bob   ").is_err());
+    }
+
+    #[test]
+    fn overflow() {
+        let p = Payment { name: "Johnathan Doe-Smith".to_string(), amount: 1.0e12, ..john() };
+
+        let Err(Error::Overflow(overflows)) = to_string(&p) else {
+            panic!("Expected overflow");
+        };
+        assert_eq!(overflows, [
+            Overflow { field: "name", width: 16, value: "Johnathan Doe-Smith".to_string() },
+            Overflow { field: "amount", width: 10, value: "1000000000000.0".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn bad_field() {
+        let result = from_str::<Payment>("John Doe        4x3   -1234.5 GBP");
+        let Err(Error::Message(msg)) = result else { panic!("Expected an error") };
+        assert_eq!(msg, r#"invalid age "4x3": invalid digit found in string"#);
+    }
+
+    #[test]
+    fn read_1252() -> Result<(), Error> {
+        let f = File::open(DATA.join("file-fixed-1252.txt"))?;
+
+        let v = read_records::<Payment, _>(f, WINDOWS_1252).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(v, [
+            john(),
+            Payment {
+                name: "Zoë Ångström".to_string(),
+                age: 7,
+                amount: 20.25,
+                currency: Currency::Eur,
+                reference: None,
+            },
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn write_1252() -> Result<(), Error> {
+        let f = File::open(DATA.join("file-fixed-1252.txt"))?;
+        let v = read_records::<Payment, _>(f, WINDOWS_1252).collect::<Result<Vec<_>, _>>()?;
+
+        let mut buf = vec![];
+        write_records(&mut buf, &v, WINDOWS_1252)?;
+        assert_eq!(buf, std::fs::read(DATA.join("file-fixed-1252.txt"))?);
+
+        // Windows-1252 has € but no ₿
+        let p = Payment { name: "₿".to_string(), ..john() };
+        assert!(matches!(write_records(vec![], [&p], WINDOWS_1252), Err(Error::Unencodable(1))));
+        Ok(())
+    }
+}
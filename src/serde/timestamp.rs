@@ -0,0 +1,480 @@
+/*
+ * Date-time fields in any serde record, in a choice of wire formats:
+ *
+ *   #[serde(with = "rfc3339")]          2023-10-29T01:00:00+01:00
+ *   #[serde(with = "iso_8601")]         2023-10-29T00:00:00          (UTC, no offset)
+ *   #[serde(with = "iso_8601_tz")]      2023-10-29T01:00:00+0100
+ *   #[serde(with = "epoch_seconds")]    1698537600                   (whole seconds)
+ *   #[serde(with = "epoch_millis")]     1698537600000                (whole milliseconds)
+ *   #[serde(with = "rfc9557")]          2023-10-29T01:00:00+01:00[Europe/London]
+ *
+ * Each works for DateTime<Tz>, DateTime<Utc>, DateTime<FixedOffset>, DateTime<Local>,
+ * NaiveDateTime and NaiveDate, and has an `option` submodule for Option fields. Naive values
+ * are taken to be UTC; dates are midnight. Only RFC 9557 keeps the time zone: the others
+ * deserialize DateTime<Tz> as the same instant in UTC. FixedOffset and Local have no zone
+ * name to write, so they're written in UTC too.
+ *
+ * Some formats silently drop what they can't hold: iso_8601, iso_8601_tz and
+ * epoch_seconds truncate any fraction of a second (towards the past), epoch_millis any
+ * fraction of a millisecond. rfc3339 and rfc9557 keep nanoseconds.
+ */
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, Offset, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::de::{DeserializeOwned, Error};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::datetime::{ISO_8601, ISO_8601_TZ};
+
+/// A date-time type that can go through any of the formats
+pub trait Timestamp: Sized {
+    fn to_zoned(&self) -> DateTime<Tz>;
+    fn from_zoned(dt: DateTime<Tz>) -> Self;
+}
+
+impl Timestamp for DateTime<Tz> {
+    fn to_zoned(&self) -> DateTime<Tz> {
+        *self
+    }
+
+    fn from_zoned(dt: DateTime<Tz>) -> Self {
+        dt
+    }
+}
+
+impl Timestamp for DateTime<Utc> {
+    fn to_zoned(&self) -> DateTime<Tz> {
+        self.with_timezone(&Tz::UTC)
+    }
+
+    fn from_zoned(dt: DateTime<Tz>) -> Self {
+        dt.with_timezone(&Utc)
+    }
+}
+
+impl Timestamp for DateTime<FixedOffset> {
+    fn to_zoned(&self) -> DateTime<Tz> {
+        self.with_timezone(&Tz::UTC)
+    }
+
+    fn from_zoned(dt: DateTime<Tz>) -> Self {
+        dt.fixed_offset()
+    }
+}
+
+impl Timestamp for DateTime<Local> {
+    fn to_zoned(&self) -> DateTime<Tz> {
+        self.with_timezone(&Tz::UTC)
+    }
+
+    fn from_zoned(dt: DateTime<Tz>) -> Self {
+        dt.with_timezone(&Local)
+    }
+}
+
+impl Timestamp for NaiveDateTime {
+    fn to_zoned(&self) -> DateTime<Tz> {
+        Tz::UTC.from_utc_datetime(self)
+    }
+
+    fn from_zoned(dt: DateTime<Tz>) -> Self {
+        dt.naive_utc()
+    }
+}
+
+impl Timestamp for NaiveDate {
+    fn to_zoned(&self) -> DateTime<Tz> {
+        Tz::UTC.from_utc_datetime(&self.and_time(Default::default()))
+    }
+
+    fn from_zoned(dt: DateTime<Tz>) -> Self {
+        dt.naive_utc().date()
+    }
+}
+
+/// How one wire format writes and reads a zoned date-time
+pub trait Format {
+    type Wire: Serialize + DeserializeOwned;
+
+    fn to_wire(dt: &DateTime<Tz>) -> Self::Wire;
+    fn from_wire(wire: Self::Wire) -> Result<DateTime<Tz>, String>;
+}
+
+pub struct Rfc3339;
+pub struct Iso8601;
+pub struct Iso8601Tz;
+pub struct EpochSeconds;
+pub struct EpochMillis;
+pub struct Rfc9557;
+
+impl Format for Rfc3339 {
+    type Wire = String;
+
+    fn to_wire(dt: &DateTime<Tz>) -> String {
+        dt.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+
+    fn from_wire(wire: String) -> Result<DateTime<Tz>, String> {
+        DateTime::parse_from_rfc3339(&wire)
+            .map(|dt| dt.with_timezone(&Tz::UTC))
+            .map_err(|e| format!("invalid RFC 3339 date-time {wire:?}: {e}"))
+    }
+}
+
+impl Format for Iso8601 {
+    type Wire = String;
+
+    fn to_wire(dt: &DateTime<Tz>) -> String {
+        dt.naive_utc().format(ISO_8601).to_string()
+    }
+
+    fn from_wire(wire: String) -> Result<DateTime<Tz>, String> {
+        NaiveDateTime::parse_from_str(&wire, ISO_8601)
+            .map(|dt| Tz::UTC.from_utc_datetime(&dt))
+            .map_err(|e| format!("invalid ISO 8601 date-time {wire:?}: {e}"))
+    }
+}
+
+impl Format for Iso8601Tz {
+    type Wire = String;
+
+    fn to_wire(dt: &DateTime<Tz>) -> String {
+        dt.format(ISO_8601_TZ).to_string()
+    }
+
+    fn from_wire(wire: String) -> Result<DateTime<Tz>, String> {
+        DateTime::parse_from_str(&wire, ISO_8601_TZ)
+            .map(|dt| dt.with_timezone(&Tz::UTC))
+            .map_err(|e| format!("invalid ISO 8601 date-time {wire:?}: {e}"))
+    }
+}
+
+impl Format for EpochSeconds {
+    type Wire = i64;
+
+    // Truncates any fraction of a second, rounding down
+    fn to_wire(dt: &DateTime<Tz>) -> i64 {
+        dt.timestamp()
+    }
+
+    fn from_wire(wire: i64) -> Result<DateTime<Tz>, String> {
+        DateTime::from_timestamp(wire, 0)
+            .map(|dt| dt.with_timezone(&Tz::UTC))
+            .ok_or_else(|| format!("epoch seconds out of range: {wire}"))
+    }
+}
+
+impl Format for EpochMillis {
+    type Wire = i64;
+
+    fn to_wire(dt: &DateTime<Tz>) -> i64 {
+        dt.timestamp_millis()
+    }
+
+    fn from_wire(wire: i64) -> Result<DateTime<Tz>, String> {
+        DateTime::from_timestamp_millis(wire)
+            .map(|dt| dt.with_timezone(&Tz::UTC))
+            .ok_or_else(|| format!("epoch millis out of range: {wire}"))
+    }
+}
+
+impl Format for Rfc9557 {
+    type Wire = String;
+
+    fn to_wire(dt: &DateTime<Tz>) -> String {
+        // +00:00 not Z: in RFC 9557, Z means the local offset is unknown
+        format!("{}[{}]", dt.to_rfc3339_opts(SecondsFormat::AutoSi, false), dt.timezone().name())
+    }
+
+    // The zone must agree with the offset, unless it's Z. Other suffixes like [u-ca=iso8601]
+    // are ignored unless marked critical with `!`.
+    fn from_wire(wire: String) -> Result<DateTime<Tz>, String> {
+        let invalid = |why: &str| format!("invalid RFC 9557 date-time {wire:?}: {why}");
+
+        let (rfc3339, mut suffixes) = wire.split_once('[')
+            .ok_or_else(|| invalid("no time zone"))?;
+        let dt = DateTime::parse_from_rfc3339(rfc3339).map_err(|e| invalid(&e.to_string()))?;
+
+        let mut zone = None;
+        while !suffixes.is_empty() {
+            let (tag, rest) = suffixes.split_once(']').ok_or_else(|| invalid("unclosed ["))?;
+            let (critical, tag) = match tag.strip_prefix('!') {
+                Some(tag) => (true, tag),
+                None => (false, tag),
+            };
+            if !tag.contains('=') && zone.is_none() {
+                zone = Some(tag.parse::<Tz>().map_err(|e| invalid(&e))?);
+            } else if critical {
+                return Err(invalid(&format!("unsupported critical tag {tag}")));
+            }
+            suffixes = rest.strip_prefix('[').unwrap_or(rest);
+        }
+
+        let zone = zone.ok_or_else(|| invalid("no time zone"))?;
+        let zoned = dt.with_timezone(&zone);
+        if !rfc3339.ends_with(['Z', 'z']) && zoned.offset().fix() != *dt.offset() {
+            return Err(invalid(&format!("offset doesn't match {}", zone.name())));
+        }
+        Ok(zoned)
+    }
+}
+
+pub fn serialize<F: Format, T: Timestamp, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    F::to_wire(&value.to_zoned()).serialize(serializer)
+}
+
+pub fn deserialize<'de, F: Format, T: Timestamp, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+    let wire = F::Wire::deserialize(deserializer)?;
+    F::from_wire(wire).map(T::from_zoned).map_err(D::Error::custom)
+}
+
+pub fn serialize_option<F: Format, T: Timestamp, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+    value.as_ref().map(|v| F::to_wire(&v.to_zoned())).serialize(serializer)
+}
+
+pub fn deserialize_option<'de, F: Format, T: Timestamp, D: Deserializer<'de>>(deserializer: D) -> Result<Option<T>, D::Error> {
+    Option::<F::Wire>::deserialize(deserializer)?
+        .map(|wire| F::from_wire(wire).map(T::from_zoned).map_err(D::Error::custom))
+        .transpose()
+}
+
+// One module per format for #[serde(with = "...")]
+macro_rules! format_modules {
+    ($($module:ident => $format:ty,)*) => {
+        $(
+            pub mod $module {
+                use super::*;
+
+                pub fn serialize<T: Timestamp, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+                    super::serialize::<$format, _, _>(value, serializer)
+                }
+
+                pub fn deserialize<'de, T: Timestamp, D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+                    super::deserialize::<$format, _, _>(deserializer)
+                }
+
+                pub mod option {
+                    use super::super::*;
+
+                    pub fn serialize<T: Timestamp, S: Serializer>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error> {
+                        serialize_option::<$format, _, _>(value, serializer)
+                    }
+
+                    pub fn deserialize<'de, T: Timestamp, D: Deserializer<'de>>(deserializer: D) -> Result<Option<T>, D::Error> {
+                        deserialize_option::<$format, _, _>(deserializer)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+format_modules! {
+    rfc3339 => Rfc3339,
+    iso_8601 => Iso8601,
+    iso_8601_tz => Iso8601Tz,
+    epoch_seconds => EpochSeconds,
+    epoch_millis => EpochMillis,
+    rfc9557 => Rfc9557,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Meeting {
+        #[serde(with = "rfc9557")]
+        start: DateTime<Tz>,
+        #[serde(with = "rfc3339")]
+        created: DateTime<Utc>,
+        #[serde(with = "iso_8601")]
+        updated: NaiveDateTime,
+        #[serde(with = "iso_8601_tz")]
+        confirmed: DateTime<Tz>,
+        #[serde(with = "epoch_millis")]
+        seen: DateTime<Tz>,
+        #[serde(with = "epoch_seconds")]
+        day: NaiveDate,
+        #[serde(with = "rfc9557::option", default)]
+        cancelled: Option<DateTime<Tz>>,
+    }
+
+    // 01:00 BST, the last hour before clocks go back
+    fn london() -> DateTime<Tz> {
+        Europe::London.with_ymd_and_hms(2023, 10, 29, 1, 0, 0).earliest().unwrap()
+    }
+
+    fn meeting() -> Meeting {
+        Meeting {
+            start: london(),
+            created: london().with_timezone(&Utc),
+            updated: london().naive_utc(),
+            confirmed: london(),
+            seen: london(),
+            day: london().date_naive(),
+            cancelled: None,
+        }
+    }
+
+    #[test]
+    fn serialize_formats() -> Result<(), serde_json::Error> {
+        assert_eq!(serde_json::to_value(meeting())?, json!({
+            "start": "2023-10-29T01:00:00+01:00[Europe/London]",
+            "created": "2023-10-29T00:00:00Z",
+            "updated": "2023-10-29T00:00:00",
+            "confirmed": "2023-10-29T01:00:00+0100",
+            "seen": 1698537600000_i64,
+            "day": 1698537600,
+            "cancelled": null,
+        }));
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), serde_json::Error> {
+        let m = Meeting { cancelled: Some(london()), ..meeting() };
+        let json = serde_json::to_string(&m)?;
+
+        let m2: Meeting = serde_json::from_str(&json)?;
+        // DateTime equality is by instant...
+        assert_eq!(m2, m);
+        // ...but only RFC 9557 keeps the zone
+        assert_eq!(m2.start.timezone(), Europe::London);
+        assert_eq!(m2.cancelled.map(|dt| dt.timezone()), Some(Europe::London));
+        assert_eq!(m2.confirmed.timezone(), Tz::UTC);
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Optional {
+        #[serde(with = "rfc3339::option")]
+        rfc3339: Option<DateTime<Tz>>,
+        #[serde(with = "iso_8601::option")]
+        iso_8601: Option<DateTime<Tz>>,
+        #[serde(with = "iso_8601_tz::option")]
+        iso_8601_tz: Option<DateTime<Tz>>,
+        #[serde(with = "epoch_seconds::option")]
+        epoch_seconds: Option<DateTime<Tz>>,
+        #[serde(with = "epoch_millis::option")]
+        epoch_millis: Option<DateTime<Tz>>,
+        #[serde(with = "rfc9557::option")]
+        rfc9557: Option<DateTime<Tz>>,
+    }
+
+    #[test]
+    fn option_round_trip() -> Result<(), serde_json::Error> {
+        for dt in [Some(london()), None] {
+            let o = Optional {
+                rfc3339: dt,
+                iso_8601: dt,
+                iso_8601_tz: dt,
+                epoch_seconds: dt,
+                epoch_millis: dt,
+                rfc9557: dt,
+            };
+            let json = serde_json::to_value(&o)?;
+            if dt.is_none() {
+                assert!(json.as_object().unwrap().values().all(|v| v.is_null()), "{json}");
+            }
+            assert_eq!(serde_json::from_value::<Optional>(json)?, o);
+        }
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Offsets {
+        #[serde(with = "rfc3339")]
+        fixed: DateTime<FixedOffset>,
+        #[serde(with = "epoch_millis::option")]
+        local: Option<DateTime<Local>>,
+    }
+
+    #[test]
+    fn fixed_and_local() -> Result<(), serde_json::Error> {
+        let o = Offsets {
+            fixed: london().fixed_offset(),
+            local: Some(london().with_timezone(&Local)),
+        };
+        let json = serde_json::to_value(&o)?;
+        assert_eq!(json, json!({ "fixed": "2023-10-29T00:00:00Z", "local": 1698537600000_i64 }));
+        // The same instants
+        assert_eq!(serde_json::from_value::<Offsets>(json)?, o);
+        Ok(())
+    }
+
+    #[test]
+    fn epoch_truncates() -> Result<(), serde_json::Error> {
+        #[derive(Serialize)]
+        struct Epochs {
+            #[serde(with = "epoch_seconds")]
+            seconds: DateTime<Utc>,
+            #[serde(with = "epoch_millis")]
+            millis: DateTime<Utc>,
+        }
+
+        let dt = DateTime::from_timestamp(-2, 999_999_999).unwrap();
+        assert_eq!(serde_json::to_value(Epochs { seconds: dt, millis: dt })?, json!({
+            "seconds": -2,
+            "millis": -1001,
+        }));
+        Ok(())
+    }
+
+    #[test]
+    fn iso_8601_truncates() -> Result<(), serde_json::Error> {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Iso {
+            #[serde(with = "iso_8601")]
+            at: NaiveDateTime,
+        }
+
+        let at = london().naive_utc() + chrono::Duration::milliseconds(1250);
+        let json = serde_json::to_value(Iso { at })?;
+        assert_eq!(json, json!({ "at": "2023-10-29T00:00:01" }));
+        // The milliseconds are gone
+        let back: Iso = serde_json::from_value(json)?;
+        assert_eq!(at - back.at, chrono::Duration::milliseconds(250));
+        Ok(())
+    }
+
+    #[test]
+    fn rfc9557_ambiguous_local_time() -> Result<(), String> {
+        // 01:00 happens twice on 2023-10-29 in London: the offset picks which
+        let bst = Rfc9557::from_wire("2023-10-29T01:00:00+01:00[Europe/London]".to_string())?;
+        let gmt = Rfc9557::from_wire("2023-10-29T01:00:00+00:00[Europe/London]".to_string())?;
+        assert_eq!(gmt - bst, chrono::Duration::hours(1));
+        assert_eq!(Rfc9557::to_wire(&gmt), "2023-10-29T01:00:00+00:00[Europe/London]");
+        Ok(())
+    }
+
+    #[test]
+    fn rfc9557_invalid() {
+        let parse = |s: &str| Rfc9557::from_wire(s.to_string());
+
+        assert!(parse("2023-10-29T01:00:00+01:00").is_err());
+        assert!(parse("2023-10-29T01:00:00+01:00[Europe/Narnia]").is_err());
+        // Offset inconsistent with zone
+        assert!(parse("2023-10-29T01:00:00+02:00[Europe/London]").is_err());
+        // Z is UTC with the zone only for local time
+        assert_eq!(parse("2023-10-29T00:00:00Z[Europe/London]"), Ok(london()));
+        // Non-critical extra tag ignored, critical one rejected
+        assert!(parse("2023-10-29T01:00:00+01:00[!Europe/London][u-ca=iso8601]").is_ok());
+        assert!(parse("2023-10-29T01:00:00+01:00[Europe/London][!u-ca=hebrew]").is_err());
+    }
+
+    #[test]
+    fn deserialize_invalid() {
+        let result = serde_json::from_value::<Meeting>(json!({
+            "start": "2023-10-29T01:00:00+01:00[Europe/London]",
+            "created": "29/10/2023",
+            "updated": "2023-10-29T00:00:00",
+            "confirmed": "2023-10-29T01:00:00+0100",
+            "seen": 1698537600000_i64,
+            "day": 1698537600,
+        }));
+        let msg = result.unwrap_err().to_string();
+        assert!(msg.starts_with(r#"invalid RFC 3339 date-time "29/10/2023""#), "{msg}");
+    }
+}
@@ -0,0 +1,126 @@
+/*
+ * XML via quick-xml's serde support. Element and attribute names come from serde
+ * annotations: `#[serde(rename = "@id")]` makes a field an attribute, and a Vec field
+ * renamed to the singular (`phone`) becomes repeated sibling elements.
+ */
+use std::io::{self, BufReader};
+
+use quick_xml::se::Serializer;
+use quick_xml::DeError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Indented, with an XML declaration. The root element is named after the type,
+/// so `#[serde(rename = "...")]` it if need be.
+fn to_string<T: Serialize>(value: &T) -> Result<String, DeError> {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let mut ser = Serializer::new(&mut xml);
+    ser.indent(' ', 2);
+    value.serialize(ser)?;
+    xml.push('\n');
+    Ok(xml)
+}
+
+/// UTF-8 only. The root element's name isn't checked.
+fn from_reader<T: DeserializeOwned>(rdr: impl io::Read) -> Result<T, DeError> {
+    quick_xml::de::from_reader(BufReader::new(rdr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::path::PathBuf;
+    use std::sync::LazyLock;
+
+    use serde::Deserialize;
+
+    static DATA: LazyLock<PathBuf, fn() -> PathBuf> =
+        LazyLock::new(|| std::env::current_dir().unwrap().join("data"));
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+    struct Address {
+        street: String,
+        city: String,
+    }
+
+    #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename = "person")]
+    struct Person {
+        // An attribute in XML
+        #[serde(rename = "@id", default, skip_serializing_if = "Option::is_none")]
+        id: Option<u32>,
+        name: String,
+        age: u8,
+        address: Address,
+        // <phone> elements in XML, and "phone" in JSON too. "phones" is accepted when reading.
+        #[serde(rename = "phone", alias = "phones", default)]
+        phones: Vec<String>,
+    }
+
+    fn john() -> Person {
+        Person {
+            id: None,
+            name: "John Doe".to_string(),
+            age: 43,
+            address: Address { street: "10 Downing Street".to_string(), city: "London".to_string() },
+            phones: vec!["+44 1234567".to_string(), "+44 2345678".to_string()],
+        }
+    }
+
+    #[test]
+    fn read_xml() -> Result<(), Box<dyn std::error::Error>> {
+        let p: Person = from_reader(File::open(DATA.join("file.xml"))?)?;
+        assert_eq!(p, john());
+        Ok(())
+    }
+
+    #[test]
+    fn read_xml_same_as_json() -> Result<(), Box<dyn std::error::Error>> {
+        let from_xml: Person = from_reader(File::open(DATA.join("file.xml"))?)?;
+        let from_json: Person = serde_json::from_reader(File::open(DATA.join("file.json"))?)?;
+        assert_eq!(from_xml, from_json);
+        Ok(())
+    }
+
+    #[test]
+    fn write_xml() -> Result<(), Box<dyn std::error::Error>> {
+        let xml = to_string(&john())?;
+        assert_eq!(xml, fs::read_to_string(DATA.join("file.xml"))?);
+        Ok(())
+    }
+
+    #[test]
+    fn attributes() -> Result<(), DeError> {
+        let p = Person { id: Some(7), ..john() };
+
+        let xml = to_string(&p)?;
+        assert!(xml.contains("<person id=\"7\">"), "{xml}");
+        assert_eq!(from_reader::<Person>(xml.as_bytes())?, p);
+        Ok(())
+    }
+
+    #[test]
+    fn phones_repeated_or_absent() -> Result<(), DeError> {
+        let xml = "<person><name>Jane</name><age>7</age>\
+            <address><street>1 Main St</street><city>Leeds</city></address></person>";
+        let p: Person = from_reader(xml.as_bytes())?;
+        assert!(p.phones.is_empty());
+
+        // Needs quick-xml's overlapped-lists feature
+        let xml = "<person><name>Jane</name><phone>1</phone><age>7</age>\
+            <address><street>1 Main St</street><city>Leeds</city></address><phone>2</phone></person>";
+        let p: Person = from_reader(xml.as_bytes())?;
+        assert_eq!(p.phones, ["1", "2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn read_xml_wrong() {
+        let xml = "<person><name>Jane</name><age>seven</age></person>";
+        assert!(from_reader::<Person>(xml.as_bytes()).is_err());
+
+        let xml = "<person><name>Jane</name><age>7</age></person>";
+        assert!(matches!(from_reader::<Person>(xml.as_bytes()), Err(DeError::Custom(msg)) if msg == "missing field `address`"));
+    }
+}
@@ -1,55 +1,65 @@
 #![allow(unused)]
 
+mod by_key;
+pub mod collate;
+pub mod external;
+mod group;
+#[cfg(any(test, feature = "laws"))]
+pub mod laws;
+mod merge;
+mod parallel;
+mod permute;
+mod radix;
+mod select;
+mod sorted_vec;
+pub mod spec;
+pub mod total;
+
 use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
 
+use key_ord::KeyOrd;
+
+/// What the Ord-based sorts and merges compare with, T::cmp, as a nameable type
+pub type CmpFn<T> = fn(&T, &T) -> Ordering;
+
 #[derive(Debug, Copy, Clone)]
 struct NotEq(f64);
 
-#[derive(Debug, Clone)]
+// Deriving the std traits would use every field, and NotEq has none of them anyway.
+// KeyOrd derives PartialEq, Eq, Hash, PartialOrd and Ord from the #[key] fields only,
+// so they can't disagree with each other.
+#[derive(Debug, Clone, KeyOrd)]
 struct KeyValue {
+    #[key]
     key: &'static str,
     #[allow(dead_code)]
     value: NotEq,
 }
 
-impl PartialEq for KeyValue {
-    fn eq(&self, other: &Self) -> bool {
-        self.key == other.key
-    }
-}
-
-// can't derive Eq since it compile-time checks that all fields are total Eq
-impl Eq for KeyValue {}
-
-// can't derive Hash since it compile-time checks that all fields are Hash
-impl Hash for KeyValue {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.key.hash(state);
-    }
-}
-
-// mustn't derive PartialOrd: it uses all fields even if Ord doesn't
-// Clippy (only) forbids this by default
-impl PartialOrd for KeyValue {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-// mustn't derive Ord: it uses all fields even if PartialOrd doesn't
-// Clippy (only) forbids this by default
-impl Ord for KeyValue {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.key.cmp(other.key)
-    }
-}
-
 const A1: KeyValue = KeyValue { key: "A", value: NotEq(1.0) };
 const A2: KeyValue = KeyValue { key: "A", value: NotEq(2.0) };
 const B2: KeyValue = KeyValue { key: "B", value: NotEq(2.0) };
 const B0: KeyValue = KeyValue { key: "B", value: NotEq(0.0) };
 
+/// Deterministic pseudo-random numbers (xorshift) for tests and benches
+#[cfg(test)]
+fn random(n: usize, mut seed: u64) -> Vec<u64> {
+    (0..n).map(|_| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    }).collect()
+}
+
+/// Random keys below `keys`, so plenty of duplicates, each with its position as a witness
+/// for stability
+#[cfg(test)]
+fn keyed(n: usize, seed: u64, keys: u64) -> Vec<(u64, usize)> {
+    random(n, seed).into_iter().enumerate().map(|(i, r)| (r % keys, i)).collect()
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -118,4 +128,40 @@ mod tests {
         assert!(a[last].is_nan() && a[last].is_sign_positive());
         assert_eq!(&a[1..last], [-f64::INFINITY, -2.4e32, -0.0, 0.0, 2.6, 5.3_f64, f64::INFINITY]);
     }
+
+    fn by_abs(a: &i32, b: &i32) -> Ordering {
+        a.abs().cmp(&b.abs())
+    }
+
+    #[derive(Debug, Clone, Copy, KeyOrd)]
+    struct Sample {
+        #[key]
+        group: u8,
+        #[key(reverse)]
+        rank: u8,
+        #[key(cmp = by_abs)]
+        delta: i32,
+        weight: NotEq,
+    }
+
+    const fn sample(group: u8, rank: u8, delta: i32) -> Sample {
+        Sample { group, rank, delta, weight: NotEq(f64::NAN) }
+    }
+
+    #[test]
+    fn derive_key_ord_multi_key() {
+        let mut a = [sample(2, 1, 0), sample(1, 1, 5), sample(1, 3, -2), sample(1, 1, -4)];
+        a.sort();
+        let keys = a.map(|s| (s.group, s.rank, s.delta));
+        assert_eq!(keys, [(1, 3, -2), (1, 1, -4), (1, 1, 5), (2, 1, 0)]);
+    }
+
+    #[test]
+    fn derive_key_ord_custom_cmp() {
+        assert_eq!(sample(1, 1, -3), sample(1, 1, 3));
+        assert_eq!(sample(1, 1, -3).cmp(&sample(1, 1, 3)), Ordering::Equal);
+        assert_eq!(do_hash(sample(1, 1, -3)), do_hash(sample(1, 1, 3)));
+        assert!(sample(1, 1, -3) > sample(1, 1, 2));
+        assert!(sample(1, 2, 0) < sample(1, 1, 0));
+    }
 }
@@ -0,0 +1,206 @@
+/*
+ * A wrapper whose PartialEq, Eq, Hash, PartialOrd and Ord all come from one key
+ * extractor, so they can't disagree the way hand-written impls like KeyValue's can.
+ *
+ * The key extractor is a type implementing KeyFn rather than a closure, so that it's part
+ * of the wrapper's type: two ByKey<T, F> always compare by the same key.
+ */
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+pub trait KeyFn<T: ?Sized> {
+    type Key<'a>: Ord + Hash where T: 'a;
+
+    fn key(value: &T) -> Self::Key<'_>;
+}
+
+pub struct ByKey<T, F> {
+    inner: T,
+    // fn() -> F: no F bounds needed for Send, Sync etc.
+    key_fn: PhantomData<fn() -> F>,
+}
+
+impl<T, F: KeyFn<T>> ByKey<T, F> {
+    pub fn new(value: T) -> Self {
+        Self { inner: value, key_fn: PhantomData }
+    }
+
+    pub fn key(&self) -> F::Key<'_> {
+        F::key(&self.inner)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T, F> Deref for ByKey<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+// Not derived: derive would add F: Clone etc. bounds
+impl<T: Clone, F> Clone for ByKey<T, F> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), key_fn: PhantomData }
+    }
+}
+
+impl<T: Copy, F> Copy for ByKey<T, F> {}
+
+impl<T: fmt::Debug, F> fmt::Debug for ByKey<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl<T, F: KeyFn<T>> PartialEq for ByKey<T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<T, F: KeyFn<T>> Eq for ByKey<T, F> {}
+
+impl<T, F: KeyFn<T>> Hash for ByKey<T, F> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl<T, F: KeyFn<T>> PartialOrd for ByKey<T, F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T, F: KeyFn<T>> Ord for ByKey<T, F> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// Stable
+pub fn sort_by_key_fn<F: KeyFn<T>, T>(v: &mut [T]) {
+    v.sort_by(|a, b| F::key(a).cmp(&F::key(b)));
+}
+
+/// Removes consecutive elements with equal keys, keeping the first of each run
+pub fn dedup_by_key_fn<F: KeyFn<T>, T>(v: &mut Vec<T>) {
+    v.dedup_by(|b, a| F::key(a) == F::key(b));
+}
+
+/// Keeps the first of each key
+pub fn hash_set_by_key<F: KeyFn<T>, T>(iter: impl IntoIterator<Item = T>) -> HashSet<ByKey<T, F>> {
+    let mut set = HashSet::new();
+    for value in iter {
+        // insert doesn't replace an equal element
+        set.insert(ByKey::new(value));
+    }
+    set
+}
+
+/// Keeps the first of each key
+pub fn btree_set_by_key<F: KeyFn<T>, T>(iter: impl IntoIterator<Item = T>) -> BTreeSet<ByKey<T, F>> {
+    let mut set = BTreeSet::new();
+    for value in iter {
+        set.insert(ByKey::new(value));
+    }
+    set
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::{A1, A2, B0, B2, KeyValue};
+    use std::collections::hash_map::DefaultHasher;
+
+    struct Key;
+
+    impl KeyFn<KeyValue> for Key {
+        type Key<'a> = &'static str;
+
+        fn key(kv: &KeyValue) -> &'static str {
+            kv.key
+        }
+    }
+
+    // Borrowing key: length then reverse alphabetical
+    struct LenThenRev;
+
+    impl KeyFn<String> for LenThenRev {
+        type Key<'a> = (usize, std::cmp::Reverse<&'a str>);
+
+        fn key(s: &String) -> Self::Key<'_> {
+            (s.len(), std::cmp::Reverse(s))
+        }
+    }
+
+    fn do_hash<T: Hash>(v: T) -> u64 {
+        let mut h = DefaultHasher::default();
+        v.hash(&mut h);
+        h.finish()
+    }
+
+    #[test]
+    fn consistent() {
+        let a1 = ByKey::<_, Key>::new(A1);
+        let a2 = ByKey::<_, Key>::new(A2);
+        let b0 = ByKey::<_, Key>::new(B0);
+
+        assert_eq!(a1, a2);
+        assert_eq!(a1.cmp(&a2), Ordering::Equal);
+        assert_eq!(a1.partial_cmp(&a2), Some(Ordering::Equal));
+        assert_eq!(do_hash(&a1), do_hash(&a2));
+
+        assert_ne!(a1, b0);
+        assert_eq!(a1.cmp(&b0), Ordering::Less);
+        assert_eq!(b0.partial_cmp(&a1), Some(Ordering::Greater));
+
+        // Deref to the value
+        assert_eq!(a2.value.0, 2.0);
+    }
+
+    #[test]
+    fn sort_stable() {
+        let mut a = [A2, B2, A1, B0];
+        sort_by_key_fn::<Key, _>(&mut a);
+        assert_eq!(a.map(|kv| kv.value.0), [2.0, 1.0, 2.0, 0.0]);
+
+        let mut a = [A2, B2, A1, B0].map(ByKey::<_, Key>::new);
+        a.sort();
+        assert_eq!(a.map(|kv| kv.value.0), [2.0, 1.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn dedup() {
+        let mut v = vec![A2, A1, B2, B0, A1];
+        dedup_by_key_fn::<Key, _>(&mut v);
+        assert_eq!(v.iter().map(|kv| kv.value.0).collect::<Vec<_>>(), [2.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn collect_sets() {
+        let set = hash_set_by_key::<Key, _>([A2, B2, A1, B0]);
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.get(&ByKey::new(A1)).map(|kv| kv.value.0), Some(2.0));
+
+        let set = btree_set_by_key::<Key, _>([B0, A2, B2, A1]);
+        assert_eq!(set.iter().map(|kv| kv.value.0).collect::<Vec<_>>(), [2.0, 0.0]);
+    }
+
+    #[test]
+    fn borrowed_key() {
+        let mut v: Vec<_> = ["bb", "a", "ccc", "aa", "c"].map(|s| ByKey::<_, LenThenRev>::new(s.to_string())).into();
+        v.sort();
+        let v: Vec<String> = v.into_iter().map(ByKey::into_inner).collect();
+        assert_eq!(v, ["c", "a", "bb", "aa", "ccc"]);
+    }
+}
@@ -0,0 +1,264 @@
+/*
+ * String orderings people expect, where str's byte-wise Ord gives "file10" < "file2" and
+ * "Zoe" < "adam".
+ *
+ * - natural_cmp: runs of ASCII digits compare as numbers
+ * - caseless_cmp: Unicode case folded (via unicase), so "Straße" == "STRASSE"
+ * - Collation: the Unicode Collation Algorithm with a locale's tailoring (via ICU4X), e.g.
+ *   "sv" puts "ä" after "z", "de" next to "a"
+ *
+ * Each is a comparator for sort_by. Natural and Caseless are key wrappers for sort_by_key,
+ * ByKey, SortSpec etc., and Collation::sort_key gives an owned key for the same.
+ */
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use icu_collator::options::CollatorOptions;
+use icu_collator::{Collator, CollatorBorrowed};
+use icu_locale_core::Locale;
+use icu_provider::DataError;
+use unicase::UniCase;
+
+/// Equal only for equal strings: "01" and "1" tie numerically, then compare byte-wise
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_rest, mut b_rest) = (a, b);
+    loop {
+        let (a_chunk, b_chunk) = match (next_chunk(a_rest), next_chunk(b_rest)) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some((a_chunk, a_next)), Some((b_chunk, b_next))) => {
+                (a_rest, b_rest) = (a_next, b_next);
+                (a_chunk, b_chunk)
+            }
+        };
+
+        let digits = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+        let o = if digits(a_chunk) && digits(b_chunk) {
+            // Any length, so no overflow
+            let (a_num, b_num) = (a_chunk.trim_start_matches('0'), b_chunk.trim_start_matches('0'));
+            a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num))
+        } else {
+            a_chunk.cmp(b_chunk)
+        };
+        if o.is_ne() {
+            return o;
+        }
+    }
+}
+
+/// Splits off the leading run of digits or non-digits
+fn next_chunk(s: &str) -> Option<(&str, &str)> {
+    let digit = s.chars().next()?.is_ascii_digit();
+    let end = s.find(|c: char| c.is_ascii_digit() != digit).unwrap_or(s.len());
+    Some(s.split_at(end))
+}
+
+pub fn caseless_cmp(a: &str, b: &str) -> Ordering {
+    UniCase::new(a).cmp(&UniCase::new(b))
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Natural<S>(pub S);
+
+impl<S: AsRef<str>> PartialEq for Natural<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_ref() == other.0.as_ref()
+    }
+}
+
+impl<S: AsRef<str>> Eq for Natural<S> {}
+
+// natural_cmp is only Equal for equal strings, so str's Hash agrees
+impl<S: AsRef<str>> Hash for Natural<S> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_ref().hash(state);
+    }
+}
+
+impl<S: AsRef<str>> PartialOrd for Natural<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S: AsRef<str>> Ord for Natural<S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        natural_cmp(self.0.as_ref(), other.0.as_ref())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Caseless<S>(pub S);
+
+impl<S: AsRef<str>> PartialEq for Caseless<S> {
+    fn eq(&self, other: &Self) -> bool {
+        UniCase::new(self.0.as_ref()) == UniCase::new(other.0.as_ref())
+    }
+}
+
+impl<S: AsRef<str>> Eq for Caseless<S> {}
+
+impl<S: AsRef<str>> Hash for Caseless<S> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        UniCase::new(self.0.as_ref()).hash(state);
+    }
+}
+
+impl<S: AsRef<str>> PartialOrd for Caseless<S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S: AsRef<str>> Ord for Caseless<S> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        caseless_cmp(self.0.as_ref(), other.0.as_ref())
+    }
+}
+
+#[derive(Debug)]
+pub enum CollationError {
+    Locale(icu_locale_core::ParseError),
+    /// No collation data for the locale
+    Data(DataError),
+}
+
+impl fmt::Display for CollationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CollationError::Locale(e) => write!(f, "bad locale: {e}"),
+            CollationError::Data(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for CollationError {}
+
+pub struct Collation {
+    collator: CollatorBorrowed<'static>,
+}
+
+impl Collation {
+    /// A BCP 47 locale, which can pick a tailoring, e.g. "de-u-co-phonebk"
+    pub fn new(locale: &str) -> Result<Self, CollationError> {
+        Self::with_options(locale, CollatorOptions::default())
+    }
+
+    /// Options such as strength, e.g. `Strength::Primary` to ignore accents and case
+    pub fn with_options(locale: &str, options: CollatorOptions) -> Result<Self, CollationError> {
+        let locale = Locale::try_from_str(locale).map_err(CollationError::Locale)?;
+        let collator = Collator::try_new((&locale).into(), options).map_err(CollationError::Data)?;
+        Ok(Self { collator })
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.collator.compare(a, b)
+    }
+
+    /// Compares byte-wise as `compare` does the strings, for use as a sort key
+    pub fn sort_key(&self, s: &str) -> Vec<u8> {
+        let mut key = vec![];
+        let Ok(()) = self.collator.write_sort_key_to(s, &mut key);
+        key
+    }
+}
+
+impl fmt::Debug for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Collation").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::spec::SortSpec;
+    use icu_collator::options::Strength;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    #[test]
+    fn natural() {
+        let mut v = ["file10", "file2", "file1", "file02", "file", "File3", "file1a", "10", "9"];
+        v.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(v, ["9", "10", "File3", "file", "file1", "file1a", "file02", "file2", "file10"]);
+
+        // Big numbers don't overflow
+        assert_eq!(natural_cmp("x99999999999999999999999", "x100000000000000000000000"), Ordering::Less);
+
+        v.sort_by_key(|s| std::cmp::Reverse(Natural(*s)));
+        assert_eq!(v[0], "file10");
+    }
+
+    #[test]
+    fn caseless() {
+        let mut v = ["Zoe", "adam", "Émile", "bob", "éa"];
+        v.sort();
+        assert_eq!(v, ["Zoe", "adam", "bob", "Émile", "éa"]);
+
+        v.sort_by(|a, b| caseless_cmp(a, b));
+        assert_eq!(v, ["adam", "bob", "Zoe", "éa", "Émile"]);
+
+        // Full case folding
+        assert_eq!(Caseless("Straße"), Caseless("STRASSE"));
+        let set: HashSet<_> = ["Straße", "STRASSE", "strasse"].map(Caseless).into();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn collation() -> Result<(), CollationError> {
+        let words = ["zebra", "Äpfel", "apple", "Zoe", "adam", "öl", "ost"];
+
+        let en = Collation::new("en")?;
+        let mut v = words;
+        v.sort_by(|a, b| en.compare(a, b));
+        assert_eq!(v, ["adam", "Äpfel", "apple", "öl", "ost", "zebra", "Zoe"]);
+
+        // Swedish sorts å, ä, ö after z
+        let sv = Collation::new("sv")?;
+        v.sort_by(|a, b| sv.compare(a, b));
+        assert_eq!(v, ["adam", "apple", "ost", "zebra", "Zoe", "Äpfel", "öl"]);
+
+        // Sort keys order the same
+        v.sort_by_key(|s| en.sort_key(s));
+        assert_eq!(v, ["adam", "Äpfel", "apple", "öl", "ost", "zebra", "Zoe"]);
+        Ok(())
+    }
+
+    #[test]
+    fn collation_options() -> Result<(), CollationError> {
+        let mut options = CollatorOptions::default();
+        options.strength = Some(Strength::Primary);
+        let primary = Collation::with_options("fr", options)?;
+        assert_eq!(primary.compare("résumé", "Resume"), Ordering::Equal);
+
+        assert!(matches!(Collation::new("not a locale!"), Err(CollationError::Locale(_))));
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct Person {
+        name: &'static str,
+        file: &'static str,
+    }
+
+    #[test]
+    fn key_projection() -> Result<(), CollationError> {
+        let sv = Arc::new(Collation::new("sv")?);
+        let spec = SortSpec::new()
+            .asc(move |p: &Person| sv.sort_key(p.name))
+            .asc(|p: &Person| Natural(p.file));
+
+        let mut v = vec![
+            Person { name: "Örjan", file: "a1" },
+            Person { name: "Anna", file: "a10" },
+            Person { name: "Anna", file: "a9" },
+        ];
+        spec.sort(&mut v);
+        assert_eq!(v.iter().map(|p| (p.name, p.file)).collect::<Vec<_>>(),
+            [("Anna", "a9"), ("Anna", "a10"), ("Örjan", "a1")]);
+        Ok(())
+    }
+}
@@ -0,0 +1,386 @@
+/*
+ * Stable external merge sort, for inputs bigger than memory.
+ *
+ * Records are encoded with bincode as they're read, and buffered with their encoding until
+ * its size reaches the memory budget. The buffer is then stable sorted and the encodings
+ * spilled to a temp file (a "run"). bincode is lossless where JSON isn't (NaN and the
+ * infinities become null), but it can't spill types that need deserialize_any, such as
+ * untagged enums or flattened structs.
+ *
+ * The output is a k-way merge of the runs (see merge.rs). Runs are closed until merged, and
+ * at most `fan_in` are merged at once, so that many files are open: with more runs, passes
+ * merge groups of them into longer runs until one merge can take the rest. Runs and groups
+ * are consecutive slices of the input, so breaking ties by run index keeps equal records
+ * in input order, as slice::sort does.
+ *
+ * If everything fits in one run nothing touches the disk.
+ */
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::path::PathBuf;
+use std::vec;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tempfile::{NamedTempFile, TempPath};
+
+use crate::sort::merge::{try_merge_sorted_by, TryMergeSorted};
+use crate::sort::CmpFn;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Runs merged at once unless set with `ExternalSort::fan_in`
+pub const MAX_FAN_IN: usize = 64;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// Encoding a record for a run, or decoding it back
+    Bincode(bincode::Error),
+    /// An error from the input iterator
+    Input(BoxError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => e.fmt(f),
+            Error::Bincode(e) => e.fmt(f),
+            Error::Input(e) => write!(f, "input: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Bincode(e) => Some(e),
+            Error::Input(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Bincode(e)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExternalSort {
+    /// Estimated bytes of records held before spilling a run
+    memory_budget: usize,
+    /// None for the system temp dir
+    temp_dir: Option<PathBuf>,
+    /// Most runs merged, so files open, at once
+    fan_in: usize,
+}
+
+impl ExternalSort {
+    pub fn new(memory_budget: usize) -> Self {
+        Self { memory_budget, temp_dir: None, fan_in: MAX_FAN_IN }
+    }
+
+    pub fn temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// Panics if `fan_in` is below 2, as merging one run at a time would never finish
+    pub fn fan_in(mut self, fan_in: usize) -> Self {
+        assert!(fan_in >= 2, "fan-in below 2");
+        self.fan_in = fan_in;
+        self
+    }
+
+    pub fn sort<T>(&self, iter: impl IntoIterator<Item = T>) -> Result<Sorted<T, CmpFn<T>>, Error>
+    where
+        T: Ord + Serialize + DeserializeOwned,
+    {
+        self.sort_by(iter, T::cmp)
+    }
+
+    pub fn sort_by_key<T, K, F>(&self, iter: impl IntoIterator<Item = T>, key: F)
+        -> Result<Sorted<T, impl Fn(&T, &T) -> Ordering>, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        K: Ord,
+        F: Fn(&T) -> K,
+    {
+        self.sort_by(iter, move |a, b| key(a).cmp(&key(b)))
+    }
+
+    pub fn sort_by<T, F>(&self, iter: impl IntoIterator<Item = T>, cmp: F) -> Result<Sorted<T, F>, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Fn(&T, &T) -> Ordering,
+    {
+        self.try_sort_by(iter.into_iter().map(Ok::<_, std::convert::Infallible>), cmp)
+    }
+
+    /// For fallible input such as `csv::Reader::deserialize`. Stops at the first error.
+    pub fn try_sort_by<T, E, F>(&self, iter: impl IntoIterator<Item = Result<T, E>>, cmp: F) -> Result<Sorted<T, F>, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        E: Into<BoxError>,
+        F: Fn(&T, &T) -> Ordering,
+    {
+        let mut runs = vec![];
+        // Each record with its encoding, which is what gets spilled
+        let mut buf: Vec<(T, Vec<u8>)> = vec![];
+        let mut buf_bytes = 0;
+
+        for item in iter {
+            let item = item.map_err(|e| Error::Input(e.into()))?;
+            let encoded = bincode::serialize(&item)?;
+            // The encoded length stands in for the record's heap, so small records still count
+            buf_bytes += mem::size_of::<T>() + encoded.len();
+            buf.push((item, encoded));
+            if buf_bytes >= self.memory_budget {
+                buf.sort_by(|a, b| cmp(&a.0, &b.0));
+                runs.push(self.spill(buf.drain(..).map(|(_, encoded)| Ok(encoded)))?);
+                buf_bytes = 0;
+            }
+        }
+
+        buf.sort_by(|a, b| cmp(&a.0, &b.0));
+        if runs.is_empty() {
+            let sorted: Vec<T> = buf.into_iter().map(|(item, _)| item).collect();
+            return Ok(Sorted { inner: Inner::Memory(sorted.into_iter()) });
+        }
+        if !buf.is_empty() {
+            runs.push(self.spill(buf.into_iter().map(|(_, encoded)| Ok(encoded)))?);
+        }
+
+        while runs.len() > self.fan_in {
+            runs = self.merge_pass(runs, &cmp)?;
+        }
+        let runs = runs.into_iter().map(Run::open).collect::<io::Result<Vec<_>>>()?;
+        Ok(Sorted { inner: Inner::Merge(try_merge_sorted_by(runs, cmp)) })
+    }
+
+    /// Merges each `fan_in` consecutive runs into one
+    fn merge_pass<T, F>(&self, runs: Vec<Run>, cmp: &F) -> Result<Vec<Run>, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Fn(&T, &T) -> Ordering,
+    {
+        let mut merged = Vec::with_capacity(runs.len().div_ceil(self.fan_in));
+        let mut runs = runs.into_iter().peekable();
+        while runs.peek().is_some() {
+            let group = runs.by_ref()
+                .take(self.fan_in)
+                .map(Run::open)
+                .collect::<io::Result<Vec<RunReader<T>>>>()?;
+            let items = try_merge_sorted_by(group, cmp);
+            merged.push(self.spill(items.map(|item| Ok(bincode::serialize(&item?)?)))?);
+        }
+        Ok(merged)
+    }
+
+    fn spill(&self, encoded: impl Iterator<Item = Result<Vec<u8>, Error>>) -> Result<Run, Error> {
+        let file = match &self.temp_dir {
+            Some(dir) => NamedTempFile::new_in(dir)?,
+            None => NamedTempFile::new()?,
+        };
+
+        let mut w = BufWriter::new(file);
+        let mut len = 0;
+        for record in encoded {
+            w.write_all(&record?)?;
+            len += 1;
+        }
+        let file = w.into_inner().map_err(io::IntoInnerError::into_error)?;
+        // Closed until merged; the file is deleted when the path is dropped
+        Ok(Run { path: file.into_temp_path(), len })
+    }
+}
+
+/// A spilled run: `len` bincode records
+struct Run {
+    path: TempPath,
+    len: usize,
+}
+
+impl Run {
+    fn open<T>(self) -> io::Result<RunReader<T>> {
+        let file = File::open(&self.path)?;
+        Ok(RunReader { reader: BufReader::new(file), remaining: self.len, _run: self.path, _item: PhantomData })
+    }
+}
+
+struct RunReader<T> {
+    reader: BufReader<File>,
+    remaining: usize,
+    /// Keeps the file until the run's been read
+    _run: TempPath,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Iterator for RunReader<T> {
+    type Item = bincode::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        Some(bincode::deserialize_from(&mut self.reader))
+    }
+}
+
+/// The sorted records. Reading back a spilled run can fail, so items are Results.
+pub struct Sorted<T, F> {
+    inner: Inner<T, F>,
+}
+
+enum Inner<T, F> {
+    Memory(vec::IntoIter<T>),
+    Merge(TryMergeSorted<RunReader<T>, T, bincode::Error, F>),
+}
+
+impl<T: DeserializeOwned, F: Fn(&T, &T) -> Ordering> Iterator for Sorted<T, F> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            Inner::Memory(iter) => iter.next().map(Ok),
+            Inner::Merge(merge) => merge.next().map(|r| r.map_err(Error::from)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::keyed;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct KeyValue {
+        key: String,
+        value: f64,
+    }
+
+    fn kv(key: &str, value: f64) -> KeyValue {
+        KeyValue { key: key.to_string(), value }
+    }
+
+    fn collect<T, F>(sorted: Sorted<T, F>) -> Result<Vec<T>, Error>
+    where
+        T: DeserializeOwned,
+        F: Fn(&T, &T) -> Ordering,
+    {
+        sorted.collect()
+    }
+
+    #[test]
+    fn stable() -> Result<(), Error> {
+        let input = [kv("A", 2.0), kv("B", 2.0), kv("A", 1.0), kv("B", 0.0)];
+        let expected = [kv("A", 2.0), kv("A", 1.0), kv("B", 2.0), kv("B", 0.0)];
+
+        // In memory
+        let sorted = ExternalSort::new(usize::MAX).sort_by_key(input.clone(), |kv| kv.key.clone())?;
+        assert_eq!(collect(sorted)?, expected);
+
+        // One record per run
+        let sorted = ExternalSort::new(0).sort_by_key(input, |kv| kv.key.clone())?;
+        assert_eq!(collect(sorted)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn matches_slice_sort() -> Result<(), Error> {
+        let input = keyed(10_000, 42, 100);
+
+        let mut expected = input.clone();
+        expected.sort_by_key(|&(k, _)| k);
+
+        for budget in [1_000, 64 * 1024, usize::MAX] {
+            let sorted = ExternalSort::new(budget).sort_by_key(input.iter().copied(), |&(k, _)| k)?;
+            assert_eq!(collect(sorted)?, expected, "budget {budget}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn fan_in() -> Result<(), Error> {
+        let input = keyed(1000, 7, 10);
+        let mut expected = input.clone();
+        expected.sort_by_key(|&(k, _)| k);
+
+        // One record per run: nine merge passes at 2, none at 1000. Stable through each.
+        for fan_in in [2, 3, 31, 1000] {
+            let sorter = ExternalSort::new(0).fan_in(fan_in);
+            let sorted = sorter.sort_by_key(input.iter().copied(), |&(k, _)| k)?;
+            assert_eq!(collect(sorted)?, expected, "fan-in {fan_in}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lossless() -> Result<(), Error> {
+        let input = [f64::NAN, f64::INFINITY, -0.0, f64::NEG_INFINITY, 0.0, -f64::NAN, 1.5];
+        let sorted = collect(ExternalSort::new(0).sort_by(input, f64::total_cmp)?)?;
+
+        let bits = |v: &[f64]| v.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+        let mut expected = input;
+        expected.sort_by(f64::total_cmp);
+        assert_eq!(bits(&sorted), bits(&expected));
+        Ok(())
+    }
+
+    #[test]
+    fn empty() -> Result<(), Error> {
+        let sorted = ExternalSort::new(0).sort(Vec::<u32>::new())?;
+        assert!(collect(sorted)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn temp_dir() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let sorted = ExternalSort::new(16).temp_dir(dir.path()).sort([3, 1, 2, 5, 4])?;
+        assert_eq!(collect(sorted)?, [1, 2, 3, 4, 5]);
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Person {
+        name: String,
+        age: u8,
+    }
+
+    #[test]
+    fn csv() -> Result<(), Box<dyn std::error::Error>> {
+        let data = "name,age\nJohn Doe,43\nJane Doe,39\nJim Doe,43\nJoe Bloggs,bad\n";
+
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let sorted = ExternalSort::new(0)
+            .try_sort_by(rdr.deserialize::<Person>().take(3), |a, b| a.age.cmp(&b.age))?;
+
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        for p in sorted {
+            wtr.serialize(p?)?;
+        }
+        let out = String::from_utf8(wtr.into_inner()?)?;
+        assert_eq!(out, "name,age\nJane Doe,39\nJohn Doe,43\nJim Doe,43\n");
+
+        // Input errors stop the sort
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let e = ExternalSort::new(0).try_sort_by(rdr.deserialize::<Person>(), |a, b| a.age.cmp(&b.age));
+        assert!(matches!(e, Err(Error::Input(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+/*
+ * Aggregates over runs of equal items, e.g. per key after a sort: [A2, A1], [B2, B0].
+ *
+ * Lazy: items stream through an Aggregator and only the previous item is held, to compare
+ * with the next, so no run is ever collected. By default items are equal by their own
+ * PartialEq, which for KeyValue means equal keys. (For slices, std's chunk_by gives the runs
+ * themselves.)
+ *
+ * The hash variants group unsorted input, holding one aggregator per group.
+ */
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::AddAssign;
+
+/// A fresh aggregator is cloned for each group, and `push` is called at least once before
+/// `finish`
+pub trait Aggregator<T>: Clone {
+    type Output;
+
+    fn push(&mut self, item: &T);
+
+    fn finish(self) -> Self::Output;
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Count(usize);
+
+pub fn count() -> Count {
+    Count(0)
+}
+
+impl<T> Aggregator<T> for Count {
+    type Output = usize;
+
+    fn push(&mut self, _: &T) {
+        self.0 += 1;
+    }
+
+    fn finish(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Sum<F, N> {
+    f: F,
+    total: N,
+}
+
+pub fn sum<T, N: Default, F: Fn(&T) -> N>(f: F) -> Sum<F, N> {
+    Sum { f, total: N::default() }
+}
+
+impl<T, N: AddAssign + Clone, F: Fn(&T) -> N + Clone> Aggregator<T> for Sum<F, N> {
+    type Output = N;
+
+    fn push(&mut self, item: &T) {
+        self.total += (self.f)(item);
+    }
+
+    fn finish(self) -> N {
+        self.total
+    }
+}
+
+macro_rules! projection_aggregator {
+    ($(#[$doc:meta])* $name:ident, $ctor:ident, $bound:path, |$kept:ident, $new:ident| $replace:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Copy, Clone)]
+        pub struct $name<F, K> {
+            f: F,
+            value: Option<K>,
+        }
+
+        pub fn $ctor<T, K, F: Fn(&T) -> K>(f: F) -> $name<F, K> {
+            $name { f, value: None }
+        }
+
+        impl<T, K: $bound + Clone, F: Fn(&T) -> K + Clone> Aggregator<T> for $name<F, K> {
+            type Output = K;
+
+            fn push(&mut self, item: &T) {
+                match &self.value {
+                    Some($kept) => {
+                        let $new = (self.f)(item);
+                        if $replace {
+                            self.value = Some($new);
+                        }
+                    }
+                    None => self.value = Some((self.f)(item)),
+                }
+            }
+
+            fn finish(self) -> K {
+                self.value.expect("push called before finish")
+            }
+        }
+    };
+}
+
+// Any bound will do for First and Last
+projection_aggregator!(
+    /// The first of equal minima
+    Min, min, Ord, |kept, new| new < *kept
+);
+projection_aggregator!(
+    /// The first of equal maxima
+    Max, max, Ord, |kept, new| new > *kept
+);
+projection_aggregator!(First, first, Sized, |kept, new| false);
+projection_aggregator!(Last, last, Sized, |kept, new| true);
+
+#[derive(Debug, Copy, Clone)]
+pub struct Fold<F, S> {
+    f: F,
+    acc: S,
+}
+
+pub fn fold<T, S, F: Fn(&mut S, &T)>(init: S, f: F) -> Fold<F, S> {
+    Fold { f, acc: init }
+}
+
+impl<T, S: Clone, F: Fn(&mut S, &T) + Clone> Aggregator<T> for Fold<F, S> {
+    type Output = S;
+
+    fn push(&mut self, item: &T) {
+        (self.f)(&mut self.acc, item);
+    }
+
+    fn finish(self) -> S {
+        self.acc
+    }
+}
+
+macro_rules! tuple_aggregator {
+    ($($a:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<T, $($a: Aggregator<T>),*> Aggregator<T> for ($($a,)*) {
+            type Output = ($($a::Output,)*);
+
+            fn push(&mut self, item: &T) {
+                let ($($a,)*) = self;
+                $($a.push(item);)*
+            }
+
+            fn finish(self) -> Self::Output {
+                let ($($a,)*) = self;
+                ($($a.finish(),)*)
+            }
+        }
+    };
+}
+
+tuple_aggregator!(A);
+tuple_aggregator!(A, B);
+tuple_aggregator!(A, B, C);
+tuple_aggregator!(A, B, C, D);
+tuple_aggregator!(A, B, C, D, E);
+tuple_aggregator!(A, B, C, D, E, F);
+
+pub struct AggregateRuns<I: Iterator, S, A> {
+    iter: I,
+    same: S,
+    aggregator: A,
+    /// The first item of the next run, already read
+    next: Option<I::Item>,
+}
+
+impl<I, S, A> Iterator for AggregateRuns<I, S, A>
+where
+    I: Iterator,
+    S: FnMut(&I::Item, &I::Item) -> bool,
+    A: Aggregator<I::Item>,
+{
+    type Item = A::Output;
+
+    fn next(&mut self) -> Option<A::Output> {
+        let mut prev = self.next.take().or_else(|| self.iter.next())?;
+        let mut agg = self.aggregator.clone();
+        agg.push(&prev);
+
+        for item in self.iter.by_ref() {
+            if !(self.same)(&prev, &item) {
+                self.next = Some(item);
+                break;
+            }
+            agg.push(&item);
+            prev = item;
+        }
+        Some(agg.finish())
+    }
+}
+
+/// Runs of items equal by PartialEq
+pub fn aggregate_runs<I, A>(iter: I, aggregator: A) -> impl Iterator<Item = A::Output>
+where
+    I: IntoIterator,
+    I::Item: PartialEq,
+    A: Aggregator<I::Item>,
+{
+    aggregate_runs_by(iter, |a, b| a == b, aggregator)
+}
+
+/// Runs where `same` holds for each item and the one before
+pub fn aggregate_runs_by<I, S, A>(iter: I, same: S, aggregator: A) -> AggregateRuns<I::IntoIter, S, A>
+where
+    I: IntoIterator,
+    S: FnMut(&I::Item, &I::Item) -> bool,
+    A: Aggregator<I::Item>,
+{
+    AggregateRuns { iter: iter.into_iter(), same, aggregator, next: None }
+}
+
+/// Runs of equal keys, with each run's key
+pub fn aggregate_runs_by_key<I, K, F, A>(iter: I, key: F, aggregator: A)
+    -> impl Iterator<Item = (K, A::Output)>
+where
+    I: IntoIterator,
+    K: PartialEq + Clone,
+    F: Fn(&I::Item) -> K + Clone,
+    A: Aggregator<I::Item>,
+{
+    let same_key = key.clone();
+    aggregate_runs_by(iter, move |a, b| same_key(a) == same_key(b), (first(key), aggregator))
+}
+
+/// Groups unsorted input by Eq and Hash, in order of each group's first item, which is kept
+/// as the group's representative
+pub fn aggregate_hash<I, A>(iter: I, aggregator: A) -> Vec<(I::Item, A::Output)>
+where
+    I: IntoIterator,
+    I::Item: Eq + Hash,
+    A: Aggregator<I::Item>,
+{
+    let mut groups: HashMap<I::Item, usize> = HashMap::new();
+    let mut aggs: Vec<A> = vec![];
+    for item in iter {
+        match groups.get(&item) {
+            Some(&i) => aggs[i].push(&item),
+            None => {
+                let mut agg = aggregator.clone();
+                agg.push(&item);
+                groups.insert(item, aggs.len());
+                aggs.push(agg);
+            }
+        }
+    }
+
+    let mut firsts: Vec<_> = groups.into_iter().collect();
+    firsts.sort_unstable_by_key(|&(_, i)| i);
+    firsts.into_iter().zip(aggs).map(|((item, _), agg)| (item, agg.finish())).collect()
+}
+
+/// Groups unsorted input by key, in order of each key's first appearance
+pub fn aggregate_hash_by_key<I, K, F, A>(iter: I, key: F, aggregator: A) -> Vec<(K, A::Output)>
+where
+    I: IntoIterator,
+    K: Eq + Hash + Clone,
+    F: Fn(&I::Item) -> K,
+    A: Aggregator<I::Item>,
+{
+    let mut groups = HashMap::new();
+    let mut aggs: Vec<(K, A)> = vec![];
+    for item in iter {
+        let k = key(&item);
+        let i = *groups.entry(k.clone()).or_insert_with(|| {
+            aggs.push((k, aggregator.clone()));
+            aggs.len() - 1
+        });
+        aggs[i].1.push(&item);
+    }
+    aggs.into_iter().map(|(k, agg)| (k, agg.finish())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::total::TotalF64;
+    use crate::sort::{KeyValue, NotEq, A1, A2, B0, B2};
+
+    fn value(kv: &KeyValue) -> f64 {
+        kv.value.0
+    }
+
+    #[test]
+    fn runs() {
+        let mut a = [A2, B2, A1, B0];
+        a.sort();
+
+        // KeyValue's == compares keys only
+        let v: Vec<_> = aggregate_runs(a.clone(), (first(|kv: &KeyValue| kv.key), count(), sum(value))).collect();
+        assert_eq!(v, [("A", 2, 3.0), ("B", 2, 2.0)]);
+
+        let v: Vec<_> = aggregate_runs_by_key(a, |kv| kv.key, (
+            first(value),
+            last(value),
+            min(|kv: &KeyValue| TotalF64(kv.value.0)),
+            max(|kv: &KeyValue| TotalF64(kv.value.0)),
+            fold(String::new(), |s: &mut String, kv: &KeyValue| s.push_str(&kv.value.0.to_string())),
+        )).collect();
+        assert_eq!(v, [
+            ("A", (2.0, 1.0, TotalF64(1.0), TotalF64(2.0), "21".to_string())),
+            ("B", (2.0, 0.0, TotalF64(0.0), TotalF64(2.0), "20".to_string())),
+        ]);
+    }
+
+    #[test]
+    fn unsorted_runs() {
+        // Only consecutive items group
+        let v: Vec<_> = aggregate_runs_by_key([A2, B2, A1, B0], |kv| kv.key, count()).collect();
+        assert_eq!(v, [("A", 1), ("B", 1), ("A", 1), ("B", 1)]);
+
+        let v: Vec<_> = aggregate_runs_by([1, 2, 4, 5, 6, 9], |a, b| b - a == 1, (first(|&i| i), last(|&i| i))).collect();
+        assert_eq!(v, [(1, 2), (4, 6), (9, 9)]);
+    }
+
+    #[test]
+    fn lazy() {
+        // Endless input, and the run in progress isn't needed for the ones before it
+        let runs = aggregate_runs((0..).map(|i: u64| i / 3), sum(|&i| i));
+        assert_eq!(runs.take(4).collect::<Vec<_>>(), [0, 3, 6, 9]);
+    }
+
+    #[test]
+    fn hash() {
+        let v = aggregate_hash([A2, B2, A1, B0], sum(value));
+        assert_eq!(v.iter().map(|(kv, sum)| (kv.key, value(kv), *sum)).collect::<Vec<_>>(),
+            [("A", 2.0, 3.0), ("B", 2.0, 2.0)]);
+
+        let v = aggregate_hash_by_key([B0, A2, B2, A1], |kv| kv.key, (count(), max(|kv: &KeyValue| TotalF64(kv.value.0))));
+        assert_eq!(v, [("B", (2, TotalF64(2.0))), ("A", (2, TotalF64(2.0)))]);
+
+        assert!(aggregate_hash(Vec::<KeyValue>::new(), count()).is_empty());
+    }
+}
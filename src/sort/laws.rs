@@ -0,0 +1,304 @@
+/*
+ * Property tests for hand-written Eq, Ord and Hash impls, like KeyValue's.
+ *
+ * rustc doesn't check that PartialOrd agrees with Ord, or Hash with Eq, and Clippy only
+ * catches the obvious cases. These run the laws against values from a proptest strategy and
+ * return the violated law with a counterexample shrunk as small as proptest can make it.
+ *
+ * Transitivity needs three related values to fail, so a strategy with a small domain (a few
+ * keys, small numbers) finds more than `any::<T>()`.
+ *
+ * Public with the `laws` feature, so other crates can run the checks in their own tests.
+ * The RNG has a fixed seed, so a check passes or fails the same way on every run.
+ */
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use proptest::strategy::Strategy;
+use proptest::test_runner::{Config, TestCaseError, TestError, TestRng, TestRunner};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Law {
+    /// a == a, a.cmp(a) == Equal
+    Reflexive,
+    /// a == b iff b == a, a.cmp(b) == b.cmp(a).reverse()
+    Symmetric,
+    /// a == b && b == c implies a == c, likewise for <
+    Transitive,
+    /// a.partial_cmp(b) == Some(a.cmp(b))
+    PartialOrdAgrees,
+    /// a.cmp(b) == Equal iff a == b
+    OrdAgreesWithEq,
+    /// a == b implies hash(a) == hash(b)
+    HashAgreesWithEq,
+}
+
+impl fmt::Display for Law {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Law::Reflexive => "reflexivity",
+            Law::Symmetric => "symmetry",
+            Law::Transitive => "transitivity",
+            Law::PartialOrdAgrees => "PartialOrd agrees with Ord",
+            Law::OrdAgreesWithEq => "Ord agrees with Eq",
+            Law::HashAgreesWithEq => "Hash agrees with Eq",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum Violation<T> {
+    /// The minimal values found, not all of which may be needed
+    Law { law: Law, values: [T; 3] },
+    /// proptest gave up, e.g. the strategy rejected too many values
+    Aborted(String),
+}
+
+impl<T: fmt::Debug> fmt::Display for Violation<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Law { law, values: [a, b, c] } => write!(f,
+                "{law} violated by\n  a = {a:?}\n  b = {b:?}\n  c = {c:?}"
+            ),
+            Violation::Aborted(reason) => write!(f, "aborted: {reason}"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for Violation<T> {}
+
+/// PartialEq and Eq
+pub fn check_eq<S>(strategy: S) -> Result<(), Violation<S::Value>>
+where
+    S: Strategy + Clone,
+    S::Value: Eq + fmt::Debug,
+{
+    check(strategy, eq_violation)
+}
+
+/// PartialEq, Eq, Hash
+pub fn check_eq_hash<S>(strategy: S) -> Result<(), Violation<S::Value>>
+where
+    S: Strategy + Clone,
+    S::Value: Eq + Hash + fmt::Debug,
+{
+    check(strategy, |a, b, c| eq_violation(a, b, c).or_else(|| hash_violation(a, b)))
+}
+
+/// PartialEq, Eq, PartialOrd, Ord
+pub fn check_ord<S>(strategy: S) -> Result<(), Violation<S::Value>>
+where
+    S: Strategy + Clone,
+    S::Value: Ord + fmt::Debug,
+{
+    check(strategy, |a, b, c| eq_violation(a, b, c).or_else(|| ord_violation(a, b, c)))
+}
+
+/// All of them
+pub fn check_ord_hash<S>(strategy: S) -> Result<(), Violation<S::Value>>
+where
+    S: Strategy + Clone,
+    S::Value: Ord + Hash + fmt::Debug,
+{
+    check(strategy, |a, b, c| {
+        eq_violation(a, b, c)
+            .or_else(|| ord_violation(a, b, c))
+            .or_else(|| hash_violation(a, b))
+    })
+}
+
+fn check<S, F>(strategy: S, violation: F) -> Result<(), Violation<S::Value>>
+where
+    S: Strategy + Clone,
+    S::Value: fmt::Debug,
+    F: Fn(&S::Value, &S::Value, &S::Value) -> Option<Law>,
+{
+    // No regression files: being deterministic, a failure recurs without one
+    let config = Config { failure_persistence: None, ..Config::default() };
+    let rng = TestRng::deterministic_rng(config.rng_algorithm);
+    let mut runner = TestRunner::new_with_rng(config, rng);
+
+    let result = runner.run(&(strategy.clone(), strategy.clone(), strategy), |(a, b, c)| {
+        match violation(&a, &b, &c) {
+            Some(law) => Err(TestCaseError::fail(law.to_string())),
+            None => Ok(()),
+        }
+    });
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(TestError::Fail(_, (a, b, c))) => {
+            // Shrinking may have ended on a different law than first failed
+            let law = violation(&a, &b, &c).expect("minimal case fails");
+            Err(Violation::Law { law, values: [a, b, c] })
+        }
+        Err(TestError::Abort(reason)) => Err(Violation::Aborted(reason.to_string())),
+    }
+}
+
+fn permutations<'a, T>(a: &'a T, b: &'a T, c: &'a T) -> [(&'a T, &'a T, &'a T); 6] {
+    [(a, b, c), (a, c, b), (b, a, c), (b, c, a), (c, a, b), (c, b, a)]
+}
+
+#[allow(clippy::eq_op)]
+fn eq_violation<T: Eq>(a: &T, b: &T, c: &T) -> Option<Law> {
+    if a != a || b != b {
+        return Some(Law::Reflexive);
+    }
+    if (a == b) != (b == a) {
+        return Some(Law::Symmetric);
+    }
+    if permutations(a, b, c).into_iter().any(|(x, y, z)| x == y && y == z && x != z) {
+        return Some(Law::Transitive);
+    }
+    None
+}
+
+fn ord_violation<T: Ord>(a: &T, b: &T, c: &T) -> Option<Law> {
+    if a.cmp(a) != Ordering::Equal {
+        return Some(Law::Reflexive);
+    }
+    if a.cmp(b) != b.cmp(a).reverse() {
+        return Some(Law::Symmetric);
+    }
+    if permutations(a, b, c).into_iter().any(|(x, y, z)| x.cmp(y) == y.cmp(z) && x.cmp(z) != x.cmp(y)) {
+        return Some(Law::Transitive);
+    }
+    if a.partial_cmp(b) != Some(a.cmp(b)) || a.partial_cmp(a) != Some(Ordering::Equal) {
+        return Some(Law::PartialOrdAgrees);
+    }
+    if (a.cmp(b) == Ordering::Equal) != (a == b) {
+        return Some(Law::OrdAgreesWithEq);
+    }
+    None
+}
+
+fn hash_violation<T: Eq + Hash>(a: &T, b: &T) -> Option<Law> {
+    fn do_hash<T: Hash>(v: &T) -> u64 {
+        let mut h = DefaultHasher::default();
+        v.hash(&mut h);
+        h.finish()
+    }
+
+    (a == b && do_hash(a) != do_hash(b)).then_some(Law::HashAgreesWithEq)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::total::{NotNan, TotalF64};
+    use crate::sort::{KeyValue, NotEq};
+    use proptest::prelude::*;
+
+    fn key_values() -> impl Strategy<Value = KeyValue> + Clone {
+        (prop::sample::select(vec!["A", "B", "C"]), any::<f64>())
+            .prop_map(|(key, value)| KeyValue { key, value: NotEq(value) })
+    }
+
+    #[test]
+    fn key_value() -> Result<(), Violation<KeyValue>> {
+        check_ord_hash(key_values())
+    }
+
+    #[test]
+    fn floats() {
+        // Includes NaN, infinities and both zeros
+        check_ord_hash(any::<f64>().prop_map(TotalF64)).unwrap();
+        check_ord_hash(any::<f64>().prop_filter_map("NaN", |f| NotNan::<f64>::new(f).ok())).unwrap();
+    }
+
+    // Eq and Ord by key, PartialOrd by value
+    #[derive(Debug, Clone)]
+    struct Disagree {
+        key: u8,
+        value: u8,
+    }
+
+    impl PartialEq for Disagree {
+        fn eq(&self, other: &Self) -> bool {
+            self.key == other.key
+        }
+    }
+
+    impl Eq for Disagree {}
+
+    // Clippy (only) catches this one
+    #[allow(clippy::non_canonical_partial_ord_impl)]
+    impl PartialOrd for Disagree {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            self.value.partial_cmp(&other.value)
+        }
+    }
+
+    impl Ord for Disagree {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.key.cmp(&other.key)
+        }
+    }
+
+    // Eq by key, Hash by both. Clippy catches this one too.
+    #[allow(clippy::derived_hash_with_manual_eq)]
+    #[derive(Debug, Clone, Hash)]
+    struct BadHash {
+        key: u8,
+        value: u8,
+    }
+
+    impl PartialEq for BadHash {
+        fn eq(&self, other: &Self) -> bool {
+            self.key == other.key
+        }
+    }
+
+    impl Eq for BadHash {}
+
+    // Equal within 1
+    #[derive(Debug, Clone)]
+    struct Approx(u8);
+
+    impl PartialEq for Approx {
+        fn eq(&self, other: &Self) -> bool {
+            self.0.abs_diff(other.0) <= 1
+        }
+    }
+
+    impl Eq for Approx {}
+
+    fn law<T>(r: Result<(), Violation<T>>) -> Option<Law> {
+        match r {
+            Err(Violation::Law { law, .. }) => Some(law),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn violations() {
+        let disagree = (0..4_u8, 0..4_u8).prop_map(|(key, value)| Disagree { key, value });
+        assert_eq!(law(check_ord(disagree)), Some(Law::PartialOrdAgrees));
+
+        let bad_hash = (0..4_u8, 0..4_u8).prop_map(|(key, value)| BadHash { key, value });
+        assert_eq!(law(check_eq(bad_hash.clone())), None);
+        assert_eq!(law(check_eq_hash(bad_hash)), Some(Law::HashAgreesWithEq));
+
+        assert_eq!(law(check_eq((0..10_u8).prop_map(Approx))), Some(Law::Transitive));
+    }
+
+    #[test]
+    fn counterexample() {
+        let Err(e @ Violation::Law { .. }) = check_eq_hash((0..8_u8, 0..100_u8).prop_map(|(key, value)| BadHash { key, value })) else {
+            panic!("expected a violation");
+        };
+
+        // The seed is fixed, so so is the shrunk counterexample. proptest shrinks one field at
+        // a time, so it can't move the equal keys down together. BadHash's == ignores value.
+        let Violation::Law { values: [a, b, c], .. } = &e else { unreachable!() };
+        let fields: Vec<_> = [a, b, c].iter().map(|v| (v.key, v.value)).collect();
+        assert_eq!(fields, [(5, 0), (5, 1), (0, 0)]);
+        assert_eq!(e.to_string(), "Hash agrees with Eq violated by
+  a = BadHash { key: 5, value: 0 }
+  b = BadHash { key: 5, value: 1 }
+  c = BadHash { key: 0, value: 0 }");
+    }
+}
@@ -0,0 +1,293 @@
+/*
+ * k-way merge of sorted iterators, e.g. one per pre-sorted file.
+ *
+ * A min-heap holds the head of each source, so each item costs O(log k). Ties go to the
+ * lower source index, so if the sources are consecutive slices of some input the output is
+ * what a stable sort of the whole input would give. The heap is hand-rolled so it can compare
+ * through the iterator's one comparator: BinaryHeap would need it shared into every element,
+ * and an Rc there would make the iterators !Send.
+ *
+ * With dedup, of each run of equal items only the first (by the same tie-breaking) is kept.
+ *
+ * The try_ variants merge iterators of Results and stop at the first error. An error read
+ * while refilling the heap is returned after the item that caused the read, then nothing
+ * more, so everything before it is still in order.
+ */
+use std::cmp::Ordering;
+use std::convert::Infallible;
+use std::iter::Map;
+
+use crate::sort::CmpFn;
+
+/// Merges `sources` by Ord
+pub fn merge_sorted<I>(sources: impl IntoIterator<Item = I>) -> MergeSorted<I::IntoIter, CmpFn<I::Item>>
+where
+    I: IntoIterator,
+    I::Item: Ord,
+{
+    merge_sorted_by(sources, I::Item::cmp)
+}
+
+pub fn merge_sorted_by_key<I, K, F>(sources: impl IntoIterator<Item = I>, key: F)
+    -> MergeSorted<I::IntoIter, impl Fn(&I::Item, &I::Item) -> Ordering>
+where
+    I: IntoIterator,
+    K: Ord,
+    F: Fn(&I::Item) -> K,
+{
+    merge_sorted_by(sources, move |a, b| key(a).cmp(&key(b)))
+}
+
+/// Each source must be sorted by `cmp`
+pub fn merge_sorted_by<I, F>(sources: impl IntoIterator<Item = I>, cmp: F) -> MergeSorted<I::IntoIter, F>
+where
+    I: IntoIterator,
+    F: Fn(&I::Item, &I::Item) -> Ordering,
+{
+    let ok: fn(I::Item) -> Result<I::Item, Infallible> = Ok;
+    MergeSorted { inner: try_merge_sorted_by(sources.into_iter().map(|s| s.into_iter().map(ok)), cmp) }
+}
+
+pub fn try_merge_sorted<I, T, E>(sources: impl IntoIterator<Item = I>) -> TryMergeSorted<I::IntoIter, T, E, CmpFn<T>>
+where
+    I: IntoIterator<Item = Result<T, E>>,
+    T: Ord,
+{
+    try_merge_sorted_by(sources, T::cmp)
+}
+
+/// The Ok items of each source must be sorted by `cmp`
+pub fn try_merge_sorted_by<I, T, E, F>(sources: impl IntoIterator<Item = I>, cmp: F) -> TryMergeSorted<I::IntoIter, T, E, F>
+where
+    I: IntoIterator<Item = Result<T, E>>,
+    F: Fn(&T, &T) -> Ordering,
+{
+    let sources: Vec<_> = sources.into_iter().map(IntoIterator::into_iter).collect();
+    TryMergeSorted {
+        heads: Heads(Vec::with_capacity(sources.len())),
+        sources,
+        cmp,
+        dedup: false,
+        started: false,
+        error: None,
+    }
+}
+
+type Infallibly<I> = Map<I, fn(<I as Iterator>::Item) -> Result<<I as Iterator>::Item, Infallible>>;
+
+pub struct MergeSorted<I: Iterator, F> {
+    inner: TryMergeSorted<Infallibly<I>, I::Item, Infallible, F>,
+}
+
+impl<I: Iterator, F> MergeSorted<I, F> {
+    /// Drop items equal by `cmp` to the one before
+    pub fn dedup(self) -> Self {
+        Self { inner: self.inner.dedup() }
+    }
+}
+
+impl<I: Iterator, F: Fn(&I::Item, &I::Item) -> Ordering> Iterator for MergeSorted<I, F> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.inner.next().map(|r| match r {
+            Ok(item) => item,
+            Err(e) => match e {},
+        })
+    }
+}
+
+pub struct TryMergeSorted<I, T, E, F> {
+    sources: Vec<I>,
+    heads: Heads<T>,
+    cmp: F,
+    dedup: bool,
+    /// Sources are first read on the first call to next
+    started: bool,
+    /// Returned after the item popped with it
+    error: Option<E>,
+}
+
+impl<I, T, E, F> TryMergeSorted<I, T, E, F> {
+    /// Drop items equal by `cmp` to the one before
+    pub fn dedup(mut self) -> Self {
+        self.dedup = true;
+        self
+    }
+}
+
+impl<I, T, E, F> TryMergeSorted<I, T, E, F>
+where
+    I: Iterator<Item = Result<T, E>>,
+    F: Fn(&T, &T) -> Ordering,
+{
+    fn refill(&mut self, source: usize) {
+        match self.sources[source].next() {
+            Some(Ok(item)) => self.heads.push(item, source, &self.cmp),
+            Some(Err(e)) => self.error = Some(e),
+            None => {}
+        }
+    }
+}
+
+impl<I, T, E, F> Iterator for TryMergeSorted<I, T, E, F>
+where
+    I: Iterator<Item = Result<T, E>>,
+    F: Fn(&T, &T) -> Ordering,
+{
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            for source in 0..self.sources.len() {
+                self.refill(source);
+                if self.error.is_some() {
+                    break;
+                }
+            }
+        }
+
+        if let Some(e) = self.error.take() {
+            self.heads.0.clear();
+            self.sources.clear();
+            return Some(Err(e));
+        }
+
+        let (item, source) = self.heads.pop(&self.cmp)?;
+        self.refill(source);
+        if self.dedup {
+            while self.error.is_none() {
+                match self.heads.0.first() {
+                    Some(&(ref head, source)) if (self.cmp)(head, &item) == Ordering::Equal => {
+                        self.heads.pop(&self.cmp);
+                        self.refill(source);
+                    }
+                    _ => break,
+                }
+            }
+        }
+        Some(Ok(item))
+    }
+}
+
+/// Min-heap of (head, source index), ordered by `cmp` then the lower source. The smallest
+/// is at 0.
+struct Heads<T>(Vec<(T, usize)>);
+
+impl<T> Heads<T> {
+    fn less(a: &(T, usize), b: &(T, usize), cmp: &impl Fn(&T, &T) -> Ordering) -> bool {
+        cmp(&a.0, &b.0).then(a.1.cmp(&b.1)) == Ordering::Less
+    }
+
+    fn push(&mut self, item: T, source: usize, cmp: &impl Fn(&T, &T) -> Ordering) {
+        let heap = &mut self.0;
+        heap.push((item, source));
+        let mut i = heap.len() - 1;
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if !Self::less(&heap[i], &heap[parent], cmp) {
+                break;
+            }
+            heap.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn pop(&mut self, cmp: &impl Fn(&T, &T) -> Ordering) -> Option<(T, usize)> {
+        let heap = &mut self.0;
+        let last = heap.len().checked_sub(1)?;
+        heap.swap(0, last);
+        let min = heap.pop();
+
+        let mut i = 0;
+        loop {
+            let left = 2 * i + 1;
+            if left >= heap.len() {
+                break;
+            }
+            let right = left + 1;
+            let child = if right < heap.len() && Self::less(&heap[right], &heap[left], cmp) { right } else { left };
+            if !Self::less(&heap[child], &heap[i], cmp) {
+                break;
+            }
+            heap.swap(i, child);
+            i = child;
+        }
+        min
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::external::ExternalSort;
+    use crate::sort::{keyed, A1, A2, B0, B2};
+
+    #[test]
+    fn stable() {
+        // Equal keys come out in source order
+        let merged: Vec<_> = merge_sorted([vec![A2, B2], vec![A1], vec![B0]]).collect();
+        assert_eq!(merged, [A2, A1, B2, B0]);
+
+        let merged: Vec<_> = merge_sorted([vec![A2, B2], vec![A1], vec![B0]]).dedup().collect();
+        assert_eq!(merged, [A2, B2]);
+    }
+
+    #[test]
+    fn matches_sort() {
+        let input = keyed(5000, 11, 100);
+        let mut expected = input.clone();
+        expected.sort_by_key(|&(k, _)| k);
+
+        for n in [1, 3, 64, 5000] {
+            let sources: Vec<Vec<_>> = input.chunks(input.len().div_ceil(n))
+                .map(|c| {
+                    let mut c = c.to_vec();
+                    c.sort_by_key(|&(k, _)| k);
+                    c
+                })
+                .collect();
+
+            let merged: Vec<_> = merge_sorted_by_key(sources.clone(), |&(k, _)| k).collect();
+            assert_eq!(merged, expected, "{n} sources");
+
+            let mut deduped = expected.clone();
+            deduped.dedup_by_key(|&mut (k, _)| k);
+            let merged: Vec<_> = merge_sorted_by_key(sources, |&(k, _)| k).dedup().collect();
+            assert_eq!(merged, deduped, "{n} sources");
+        }
+    }
+
+    #[test]
+    fn send() {
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&merge_sorted([vec![1, 2]]));
+        assert_send(&try_merge_sorted([vec![Ok::<_, String>(1)]]).dedup());
+        assert_send(&ExternalSort::new(0).sort([2, 1]).unwrap());
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(merge_sorted(Vec::<Vec<u32>>::new()).next(), None);
+        let merged: Vec<_> = merge_sorted([vec![], vec![1, 3], vec![], vec![2]]).collect();
+        assert_eq!(merged, [1, 2, 3]);
+    }
+
+    #[test]
+    fn errors() {
+        let merged: Vec<Result<u32, &str>> = try_merge_sorted([
+            vec![Ok(1), Ok(4), Err("bad"), Ok(9)],
+            vec![Ok(2), Ok(3), Ok(5)],
+        ]).collect();
+        // 4 is returned before the error read in its place, then nothing
+        assert_eq!(merged, [Ok(1), Ok(2), Ok(3), Ok(4), Err("bad")]);
+
+        // Also when first reading the sources
+        let merged: Vec<Result<u32, &str>> = try_merge_sorted([vec![Ok(1)], vec![Err("bad")]]).collect();
+        assert_eq!(merged, [Err("bad")]);
+
+        let merged: Vec<Result<u32, &str>> = try_merge_sorted([vec![Ok(1), Ok(2)], vec![Ok(1)]]).dedup().collect();
+        assert_eq!(merged, [Ok(1), Ok(2)]);
+    }
+}
@@ -0,0 +1,179 @@
+/*
+ * Parallel stable merge sort on scoped threads.
+ *
+ * The slice is cut into one chunk per available thread and each chunk is sorted in its own
+ * scoped thread, as in threads.rs mutate_slices. Neighbouring sorted runs are then merged in
+ * pairs, in parallel, halving the number of runs each round.
+ *
+ * The merge is slice::sort_by on the two adjacent runs: it detects them as runs and merges
+ * in linear time, stably, with no unsafe code here. So the result is always exactly what
+ * slice::sort_by gives, including the order of equal elements.
+ */
+use std::cmp::Ordering;
+use std::num::NonZeroUsize;
+use std::thread;
+
+/// Below this length threads cost more than they save
+const SEQUENTIAL_CUTOFF: usize = 8 * 1024;
+
+pub fn par_sort<T: Ord + Send>(v: &mut [T]) {
+    par_sort_by(v, T::cmp);
+}
+
+pub fn par_sort_by_key<T, K, F>(v: &mut [T], key: F)
+where
+    T: Send,
+    K: Ord,
+    F: Fn(&T) -> K + Sync,
+{
+    par_sort_by(v, |a, b| key(a).cmp(&key(b)));
+}
+
+pub fn par_sort_by<T, F>(v: &mut [T], cmp: F)
+where
+    T: Send,
+    F: Fn(&T, &T) -> Ordering + Sync,
+{
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    par_sort_by_threads(v, &cmp, threads);
+}
+
+fn par_sort_by_threads<T, F>(v: &mut [T], cmp: &F, threads: usize)
+where
+    T: Send,
+    F: Fn(&T, &T) -> Ordering + Sync,
+{
+    if threads <= 1 || v.len() < SEQUENTIAL_CUTOFF {
+        v.sort_by(cmp);
+        return;
+    }
+
+    let mut run_len = v.len().div_ceil(threads);
+    thread::scope(|scope| {
+        for chunk in v.chunks_mut(run_len) {
+            scope.spawn(|| chunk.sort_by(cmp));
+        }
+    });
+
+    while run_len < v.len() {
+        thread::scope(|scope| {
+            for pair in v.chunks_mut(2 * run_len) {
+                // A lone last run is already sorted
+                if pair.len() > run_len {
+                    scope.spawn(|| pair.sort_by(cmp));
+                }
+            }
+        });
+        run_len *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::{keyed, A1, A2, B0, B2};
+
+    #[test]
+    fn sort_stable() {
+        let mut a = [A2, B2, A1, B0];
+        par_sort(&mut a);
+        assert_eq!(a, [A2, A1, B2, B0]);
+    }
+
+    #[test]
+    fn same_as_slice_sort() {
+        // Odd lengths so chunks and pairs don't divide evenly
+        for len in [0, 1, SEQUENTIAL_CUTOFF + 1, 100_003] {
+            let input = keyed(len, 7, 1000);
+
+            let mut expected = input.clone();
+            expected.sort_by_key(|&(k, _)| k);
+
+            for threads in [1, 2, 3, 8] {
+                let mut v = input.clone();
+                par_sort_by_threads(&mut v, &|a: &(u64, usize), b: &(u64, usize)| a.0.cmp(&b.0), threads);
+                assert_eq!(v, expected, "len {len}, threads {threads}");
+            }
+
+            let mut v = input.clone();
+            par_sort_by_key(&mut v, |&(k, _)| k);
+            assert_eq!(v, expected, "len {len}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod benches {
+    extern crate test;
+
+    use super::*;
+    use crate::sort::random;
+    use test::Bencher;
+
+    const N: usize = 200_000;
+
+    fn random_input() -> Vec<u64> {
+        random(N, 42)
+    }
+
+    fn sorted_input() -> Vec<u64> {
+        (0..N as u64).collect()
+    }
+
+    fn reversed_input() -> Vec<u64> {
+        (0..N as u64).rev().collect()
+    }
+
+    fn duplicates_input() -> Vec<u64> {
+        random(N, 42).into_iter().map(|r| r % 16).collect()
+    }
+
+    fn bench(b: &mut Bencher, input: fn() -> Vec<u64>, sort: fn(&mut [u64])) {
+        let input = input();
+        b.iter(|| {
+            let mut v = input.clone();
+            sort(&mut v);
+            v
+        });
+    }
+
+    #[bench]
+    fn random_std(b: &mut Bencher) {
+        bench(b, random_input, <[u64]>::sort);
+    }
+
+    #[bench]
+    fn random_par(b: &mut Bencher) {
+        bench(b, random_input, par_sort);
+    }
+
+    #[bench]
+    fn sorted_std(b: &mut Bencher) {
+        bench(b, sorted_input, <[u64]>::sort);
+    }
+
+    #[bench]
+    fn sorted_par(b: &mut Bencher) {
+        bench(b, sorted_input, par_sort);
+    }
+
+    #[bench]
+    fn reversed_std(b: &mut Bencher) {
+        bench(b, reversed_input, <[u64]>::sort);
+    }
+
+    #[bench]
+    fn reversed_par(b: &mut Bencher) {
+        bench(b, reversed_input, par_sort);
+    }
+
+    #[bench]
+    fn duplicates_std(b: &mut Bencher) {
+        bench(b, duplicates_input, <[u64]>::sort);
+    }
+
+    #[bench]
+    fn duplicates_par(b: &mut Bencher) {
+        bench(b, duplicates_input, par_sort);
+    }
+}
@@ -0,0 +1,278 @@
+/*
+ * Sorting through index permutations, for parallel columns (names, ages, phones) that have
+ * to stay aligned.
+ *
+ * argsort gives the indices of v in sorted order, so `perm[j]` is where the j'th smallest
+ * is. apply_permutation moves `v[perm[j]]` to `v[j]` in place by following cycles with
+ * swaps, for any number of columns at once: pass a tuple of slices. Applying argsort(v) to
+ * v sorts it.
+ *
+ * rank is the other direction: `rank[i]` is where `v[i]` would go, counting from 1 like
+ * spreadsheets and scipy's rankdata, with a choice of ranks for ties.
+ */
+use std::cmp::Ordering;
+
+/// Stable
+pub fn argsort<T: Ord>(v: &[T]) -> Vec<usize> {
+    argsort_by(v, T::cmp)
+}
+
+pub fn argsort_by_key<T, K: Ord>(v: &[T], key: impl Fn(&T) -> K) -> Vec<usize> {
+    argsort_by(v, |a, b| key(a).cmp(&key(b)))
+}
+
+/// Stable: equal elements' indices stay in order
+pub fn argsort_by<T>(v: &[T], cmp: impl Fn(&T, &T) -> Ordering) -> Vec<usize> {
+    let mut perm: Vec<usize> = (0..v.len()).collect();
+    perm.sort_by(|&i, &j| cmp(&v[i], &v[j]));
+    perm
+}
+
+pub fn argsort_unstable<T: Ord>(v: &[T]) -> Vec<usize> {
+    argsort_unstable_by(v, T::cmp)
+}
+
+pub fn argsort_unstable_by_key<T, K: Ord>(v: &[T], key: impl Fn(&T) -> K) -> Vec<usize> {
+    argsort_unstable_by(v, |a, b| key(a).cmp(&key(b)))
+}
+
+pub fn argsort_unstable_by<T>(v: &[T], cmp: impl Fn(&T, &T) -> Ordering) -> Vec<usize> {
+    let mut perm: Vec<usize> = (0..v.len()).collect();
+    perm.sort_unstable_by(|&i, &j| cmp(&v[i], &v[j]));
+    perm
+}
+
+/// Anything apply_permutation can rearrange: slices, Vecs, and tuples of them, all the same
+/// length
+pub trait Permute {
+    fn len(&self) -> usize;
+
+    fn swap(&mut self, i: usize, j: usize);
+}
+
+impl<T> Permute for [T] {
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        <[T]>::swap(self, i, j);
+    }
+}
+
+impl<T, const N: usize> Permute for [T; N] {
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        <[T]>::swap(self, i, j);
+    }
+}
+
+impl<T> Permute for Vec<T> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        <[T]>::swap(self, i, j);
+    }
+}
+
+impl<P: Permute + ?Sized> Permute for &mut P {
+    fn len(&self) -> usize {
+        P::len(self)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        P::swap(self, i, j);
+    }
+}
+
+macro_rules! permute_tuple {
+    ($first:ident $(, $rest:ident)*) => {
+        #[allow(non_snake_case)]
+        impl<$first: Permute, $($rest: Permute),*> Permute for ($first, $($rest,)*) {
+            fn len(&self) -> usize {
+                let ($first, $($rest,)*) = self;
+                $(assert_eq!($rest.len(), $first.len(), "columns differ in length");)*
+                $first.len()
+            }
+
+            fn swap(&mut self, i: usize, j: usize) {
+                let ($first, $($rest,)*) = self;
+                $first.swap(i, j);
+                $($rest.swap(i, j);)*
+            }
+        }
+    };
+}
+
+permute_tuple!(A);
+permute_tuple!(A, B);
+permute_tuple!(A, B, C);
+permute_tuple!(A, B, C, D);
+permute_tuple!(A, B, C, D, E);
+permute_tuple!(A, B, C, D, E, F);
+
+pub fn is_permutation(perm: &[usize]) -> bool {
+    let mut seen = vec![false; perm.len()];
+    perm.iter().all(|&i| i < perm.len() && !std::mem::replace(&mut seen[i], true))
+}
+
+/// Rearranges `columns` so position `j` holds what was at `perm[j]`
+///
+/// Panics if `perm` isn't a permutation of the columns' indices.
+pub fn apply_permutation<P: Permute + ?Sized>(columns: &mut P, perm: &[usize]) {
+    assert_eq!(perm.len(), columns.len(), "permutation length");
+    assert!(is_permutation(perm), "not a permutation");
+
+    let mut done = vec![false; perm.len()];
+    for start in 0..perm.len() {
+        // Carry what was at start round its cycle, pulling each element back into place
+        let mut j = start;
+        while !done[j] {
+            done[j] = true;
+            let k = perm[j];
+            if k != start {
+                columns.swap(j, k);
+            }
+            j = k;
+        }
+    }
+}
+
+/// `inverse[perm[j]] == j`: undoes apply_permutation, and turns an argsort into ordinal ranks
+/// from 0
+pub fn inverse_permutation(perm: &[usize]) -> Vec<usize> {
+    let mut inverse = vec![0; perm.len()];
+    for (j, &i) in perm.iter().enumerate() {
+        inverse[i] = j;
+    }
+    inverse
+}
+
+/// The rank given to equal elements
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Ties {
+    /// [10, 20, 20, 30] -> [1, 2, 2, 4]
+    Min,
+    /// [10, 20, 20, 30] -> [1, 3, 3, 4]
+    Max,
+    /// [10, 20, 20, 30] -> [1, 2, 2, 3]
+    Dense,
+    /// [10, 20, 20, 30] -> [1, 2.5, 2.5, 4]
+    Average,
+}
+
+/// Ranks from 1. f64 for Ties::Average; the others are whole numbers.
+pub fn rank<T: Ord>(v: &[T], ties: Ties) -> Vec<f64> {
+    rank_by(v, ties, T::cmp)
+}
+
+pub fn rank_by_key<T, K: Ord>(v: &[T], ties: Ties, key: impl Fn(&T) -> K) -> Vec<f64> {
+    rank_by(v, ties, |a, b| key(a).cmp(&key(b)))
+}
+
+pub fn rank_by<T>(v: &[T], ties: Ties, cmp: impl Fn(&T, &T) -> Ordering) -> Vec<f64> {
+    let perm = argsort_unstable_by(v, &cmp);
+    let mut ranks = vec![0.0; v.len()];
+
+    let mut start = 0;
+    for (group, run) in perm.chunk_by(|&i, &j| cmp(&v[i], &v[j]) == Ordering::Equal).enumerate() {
+        let end = start + run.len();
+        let r = match ties {
+            Ties::Min => (start + 1) as f64,
+            Ties::Max => end as f64,
+            Ties::Dense => (group + 1) as f64,
+            Ties::Average => (start + 1 + end) as f64 / 2.0,
+        };
+        for &i in run {
+            ranks[i] = r;
+        }
+        start = end;
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::{random, A1, A2, B0, B2};
+
+    #[test]
+    fn argsorts() {
+        let v = [A2, B2, A1, B0];
+        assert_eq!(argsort(&v), [0, 2, 1, 3]);
+        assert_eq!(argsort_by_key(&v, |kv| std::cmp::Reverse(kv.key)), [1, 3, 0, 2]);
+
+        let perm = argsort_unstable(&v);
+        assert!(perm.iter().map(|&i| v[i].key).eq(["A", "A", "B", "B"]));
+
+        let mut sorted = v.clone();
+        apply_permutation(&mut sorted, &argsort(&v));
+        assert_eq!(sorted, [A2, A1, B2, B0]);
+    }
+
+    #[test]
+    fn columns() {
+        let mut names = vec!["John Doe", "Jane Doe", "Jim Doe"];
+        let mut ages = vec![43, 39, 43];
+        let mut phones = vec!["555-0101", "555-0102", "555-0103"];
+
+        let perm = argsort(&ages);
+        apply_permutation(&mut (&mut names, &mut ages, &mut phones[..]), &perm);
+        assert_eq!(names, ["Jane Doe", "John Doe", "Jim Doe"]);
+        assert_eq!(ages, [39, 43, 43]);
+        assert_eq!(phones, ["555-0102", "555-0101", "555-0103"]);
+
+        // And back
+        apply_permutation(&mut (&mut names, &mut ages, &mut phones), &inverse_permutation(&perm));
+        assert_eq!(names, ["John Doe", "Jane Doe", "Jim Doe"]);
+    }
+
+    #[test]
+    fn permutations() {
+        let mut v = ['a', 'b', 'c', 'd', 'e'];
+        apply_permutation(&mut v, &[3, 0, 4, 1, 2]);
+        assert_eq!(v, ['d', 'a', 'e', 'b', 'c']);
+        assert_eq!(inverse_permutation(&[3, 0, 4, 1, 2]), [1, 3, 4, 0, 2]);
+
+        assert!(is_permutation(&[]));
+        assert!(!is_permutation(&[0, 0]));
+        assert!(!is_permutation(&[1, 2]));
+
+        let input = random(1000, 5);
+        let perm = argsort(&input);
+        let mut v = input.clone();
+        apply_permutation(&mut v, &perm);
+        assert!(v.is_sorted());
+        apply_permutation(&mut v, &inverse_permutation(&perm));
+        assert_eq!(v, input);
+    }
+
+    #[test]
+    #[should_panic(expected = "columns differ in length")]
+    fn ragged_columns() {
+        apply_permutation(&mut (vec![1, 2], vec![1]), &[1, 0]);
+    }
+
+    #[test]
+    fn ranks() {
+        let v = [20, 10, 30, 20];
+        assert_eq!(rank(&v, Ties::Min), [2.0, 1.0, 4.0, 2.0]);
+        assert_eq!(rank(&v, Ties::Max), [3.0, 1.0, 4.0, 3.0]);
+        assert_eq!(rank(&v, Ties::Dense), [2.0, 1.0, 3.0, 2.0]);
+        assert_eq!(rank(&v, Ties::Average), [2.5, 1.0, 4.0, 2.5]);
+
+        // KeyValue ties on key
+        assert_eq!(rank(&[B0, A2, A1, B2], Ties::Dense), [2.0, 1.0, 1.0, 2.0]);
+        assert!(rank::<u8>(&[], Ties::Min).is_empty());
+
+        // Without ties, ranks are the inverse of argsort
+        let v = [5, 1, 4, 2, 3];
+        let ordinal: Vec<f64> = inverse_permutation(&argsort(&v)).into_iter().map(|r| (r + 1) as f64).collect();
+        assert_eq!(rank(&v, Ties::Min), ordinal);
+    }
+}
@@ -0,0 +1,450 @@
+/*
+ * Radix sorts for numeric and byte-string keys: O(n * key bytes) instead of O(n log n).
+ *
+ * Numeric keys map to a u64 whose unsigned order is the key's order: signed ints flip the
+ * sign bit, floats use the same bit trick as total_cmp so the order matches
+ * sort_floats_total (-NaN, -inf, ..., -0.0, 0.0, ..., inf, NaN).
+ *
+ * - radix_sort_by_key: LSD, one counting pass per key byte, so stable
+ * - radix_sort_unstable_by_key: MSD in place (American flag sort), no buffer
+ * - radix_sort_bytes_by_key: MSD on byte strings, stable, same order as [u8]'s Ord
+ *
+ * Records are sorted through a permutation of indices, so T needn't be Copy or Clone.
+ */
+use std::mem;
+
+use crate::sort::permute::apply_permutation;
+use crate::sort::total::{TotalF32, TotalF64};
+
+/// Below this length a comparison sort is faster
+const SMALL: usize = 32;
+
+pub trait RadixKey: Copy {
+    /// Significant bytes of `to_radix`, from the lowest
+    const BYTES: u32;
+
+    fn to_radix(self) -> u64;
+}
+
+macro_rules! radix_key_unsigned {
+    ($($t:ty),*) => {$(
+        impl RadixKey for $t {
+            const BYTES: u32 = mem::size_of::<$t>() as u32;
+
+            fn to_radix(self) -> u64 {
+                self as u64
+            }
+        }
+    )*};
+}
+
+macro_rules! radix_key_signed {
+    ($($t:ty => $u:ty),*) => {$(
+        impl RadixKey for $t {
+            const BYTES: u32 = mem::size_of::<$t>() as u32;
+
+            fn to_radix(self) -> u64 {
+                // Flipping the sign bit puts negatives first
+                (self as $u ^ (1 << (<$u>::BITS - 1))) as u64
+            }
+        }
+    )*};
+}
+
+radix_key_unsigned!(u8, u16, u32, u64, usize);
+radix_key_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, isize => usize);
+
+impl RadixKey for bool {
+    const BYTES: u32 = 1;
+
+    fn to_radix(self) -> u64 {
+        self as u64
+    }
+}
+
+impl RadixKey for char {
+    const BYTES: u32 = 3;
+
+    fn to_radix(self) -> u64 {
+        self as u64
+    }
+}
+
+// As total_cmp: negative floats have all bits flipped so larger magnitudes sort first,
+// positive floats just the sign bit so they sort after
+impl RadixKey for f64 {
+    const BYTES: u32 = 8;
+
+    fn to_radix(self) -> u64 {
+        let bits = self.to_bits();
+        if bits >> 63 == 1 { !bits } else { bits | 1 << 63 }
+    }
+}
+
+impl RadixKey for f32 {
+    const BYTES: u32 = 4;
+
+    fn to_radix(self) -> u64 {
+        let bits = self.to_bits();
+        (if bits >> 31 == 1 { !bits } else { bits | 1 << 31 }) as u64
+    }
+}
+
+impl RadixKey for TotalF64 {
+    const BYTES: u32 = 8;
+
+    fn to_radix(self) -> u64 {
+        self.0.to_radix()
+    }
+}
+
+impl RadixKey for TotalF32 {
+    const BYTES: u32 = 4;
+
+    fn to_radix(self) -> u64 {
+        self.0.to_radix()
+    }
+}
+
+fn byte(key: u64, shift: u32) -> usize {
+    (key >> shift) as usize & 0xff
+}
+
+/// Stable
+pub fn radix_sort<K: RadixKey>(v: &mut [K]) {
+    lsd(v, K::BYTES, |k| k.to_radix());
+}
+
+/// Stable
+pub fn radix_sort_by_key<T, K: RadixKey>(v: &mut [T], key: impl Fn(&T) -> K) {
+    let mut keyed: Vec<(u64, usize)> = v.iter().enumerate().map(|(i, t)| (key(t).to_radix(), i)).collect();
+    lsd(&mut keyed, K::BYTES, |&(k, _)| k);
+    let perm: Vec<usize> = keyed.into_iter().map(|(_, i)| i).collect();
+    apply_permutation(v, &perm);
+}
+
+fn lsd<E: Copy>(v: &mut [E], bytes: u32, key: impl Fn(&E) -> u64) {
+    if v.len() <= SMALL {
+        v.sort_by_key(key);
+        return;
+    }
+
+    // Every byte's counts in one read, since the passes don't change them
+    let mut counts = vec![[0; 256]; bytes as usize];
+    for e in v.iter() {
+        let k = key(e);
+        for (b, c) in counts.iter_mut().enumerate() {
+            c[byte(k, b as u32 * 8)] += 1;
+        }
+    }
+
+    let mut buf = v.to_vec();
+    let mut in_buf = false;
+    for (shift, counts) in counts.iter().enumerate().map(|(b, c)| (b as u32 * 8, c)) {
+        // All the same byte: this pass wouldn't move anything
+        if counts.contains(&v.len()) {
+            continue;
+        }
+        let (src, dst) = if in_buf { (&buf[..], &mut v[..]) } else { (&v[..], &mut buf[..]) };
+
+        let mut offsets = [0; 256];
+        for b in 1..256 {
+            offsets[b] = offsets[b - 1] + counts[b - 1];
+        }
+        for e in src {
+            let b = byte(key(e), shift);
+            dst[offsets[b]] = *e;
+            offsets[b] += 1;
+        }
+        in_buf = !in_buf;
+    }
+
+    if in_buf {
+        v.copy_from_slice(&buf);
+    }
+}
+
+pub fn radix_sort_unstable<K: RadixKey>(v: &mut [K]) {
+    radix_sort_unstable_by_key(v, |&k| k);
+}
+
+/// In place, calling `key` O(key bytes) times per element
+pub fn radix_sort_unstable_by_key<T, K: RadixKey>(v: &mut [T], key: impl Fn(&T) -> K) {
+    if K::BYTES > 0 {
+        msd(v, &|t| key(t).to_radix(), (K::BYTES - 1) * 8);
+    }
+}
+
+fn msd<T>(v: &mut [T], key: &impl Fn(&T) -> u64, shift: u32) {
+    if v.len() <= SMALL {
+        // Higher bytes are all equal here, so comparing whole keys is fine
+        v.sort_unstable_by_key(key);
+        return;
+    }
+
+    let mut counts = [0; 256];
+    for t in v.iter() {
+        counts[byte(key(t), shift)] += 1;
+    }
+
+    let mut heads = [0; 256];
+    let mut tails = [0; 256];
+    let mut start = 0;
+    for b in 0..256 {
+        heads[b] = start;
+        start += counts[b];
+        tails[b] = start;
+    }
+
+    // Swap each element straight into its bucket
+    for b in 0..256 {
+        while heads[b] < tails[b] {
+            let dest = byte(key(&v[heads[b]]), shift);
+            if dest == b {
+                heads[b] += 1;
+            } else {
+                v.swap(heads[b], heads[dest]);
+                heads[dest] += 1;
+            }
+        }
+    }
+
+    if shift > 0 {
+        let mut start = 0;
+        for &end in &tails {
+            msd(&mut v[start..end], key, shift - 8);
+            start = end;
+        }
+    }
+}
+
+/// Stable
+pub fn radix_sort_bytes<T: AsRef<[u8]>>(v: &mut [T]) {
+    radix_sort_bytes_by_key(v, |t| t.as_ref());
+}
+
+/// Stable, ordered as `[u8]`: a prefix comes before anything longer
+pub fn radix_sort_bytes_by_key<T, F>(v: &mut [T], key: F)
+where
+    F: Fn(&T) -> &[u8],
+{
+    let mut indices: Vec<usize> = (0..v.len()).collect();
+    let mut buf = vec![0; v.len()];
+    msd_bytes(&mut indices, &mut buf, &|i| key(&v[i]));
+    apply_permutation(v, &indices);
+}
+
+/// Iterative, with the buckets still to sort on a stack, as keys sharing a long prefix would
+/// otherwise recurse once per shared byte
+fn msd_bytes<'a>(indices: &mut [usize], buf: &mut [usize], key: &dyn Fn(usize) -> &'a [u8]) {
+    // (start, end, depth) of each bucket of indices still to sort
+    let mut stack = vec![(0, indices.len(), 0)];
+    while let Some((start, end, depth)) = stack.pop() {
+        let (indices, buf) = (&mut indices[start..end], &mut buf[start..end]);
+        if indices.len() <= SMALL {
+            indices.sort_by(|&i, &j| key(i)[depth..].cmp(&key(j)[depth..]));
+            continue;
+        }
+
+        // Bucket 0 for keys that have ended, before any byte
+        let bucket = |i: usize| key(i).get(depth).map_or(0, |&b| b as usize + 1);
+
+        let mut counts = [0; 257];
+        for &i in indices.iter() {
+            counts[bucket(i)] += 1;
+        }
+        let mut offsets = [0; 257];
+        for b in 1..257 {
+            offsets[b] = offsets[b - 1] + counts[b - 1];
+        }
+
+        for &i in indices.iter() {
+            let b = bucket(i);
+            buf[offsets[b]] = i;
+            offsets[b] += 1;
+        }
+        indices.copy_from_slice(buf);
+
+        // offsets are now the bucket ends. Ended keys are all equal: leave them in input order.
+        let mut bucket_start = counts[0];
+        for &bucket_end in &offsets[1..] {
+            if bucket_end - bucket_start > 1 {
+                stack.push((start + bucket_start, start + bucket_end, depth + 1));
+            }
+            bucket_start = bucket_end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::{keyed, random};
+
+    #[test]
+    fn integers() {
+        let mut v = random(10_000, 1);
+        let mut expected = v.clone();
+        expected.sort_unstable();
+
+        let mut stable = v.clone();
+        radix_sort(&mut stable);
+        assert_eq!(stable, expected);
+
+        radix_sort_unstable(&mut v);
+        assert_eq!(v, expected);
+
+        let mut v: Vec<i32> = random(10_000, 2).into_iter().map(|r| r as i32).collect();
+        v.extend([i32::MIN, i32::MAX, 0, -1]);
+        let mut expected = v.clone();
+        expected.sort_unstable();
+        radix_sort(&mut v);
+        assert_eq!(v, expected);
+
+        let mut v: Vec<i8> = random(1000, 3).into_iter().map(|r| r as i8).collect();
+        let mut expected = v.clone();
+        expected.sort_unstable();
+        radix_sort_unstable(&mut v);
+        assert_eq!(v, expected);
+    }
+
+    #[test]
+    fn floats_total() {
+        // As sort_floats_total
+        let specials = [5.3, 2.6, 0.0, f64::NEG_INFINITY, f64::INFINITY, -2.4e32, f64::NAN, -0.0, -f64::NAN];
+        let mut v: Vec<f64> = random(5_000, 4).into_iter().map(f64::from_bits).collect();
+        v.extend(specials);
+        let mut expected = v.clone();
+        expected.sort_by(f64::total_cmp);
+        let bits = |v: &[f64]| v.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+
+        let mut stable = v.clone();
+        radix_sort(&mut stable);
+        assert_eq!(bits(&stable), bits(&expected));
+
+        radix_sort_unstable(&mut v);
+        assert_eq!(bits(&v), bits(&expected));
+
+        let mut small = specials;
+        radix_sort(&mut small);
+        assert!(small[0].is_nan() && small[0].is_sign_negative());
+        assert_eq!(small[1..8], [f64::NEG_INFINITY, -2.4e32, -0.0, 0.0, 2.6, 5.3, f64::INFINITY]);
+
+        let mut v: Vec<TotalF32> = random(1000, 5).into_iter().map(|r| TotalF32(f32::from_bits(r as u32))).collect();
+        let mut expected = v.clone();
+        expected.sort();
+        radix_sort(&mut v);
+        assert_eq!(v, expected);
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Reading {
+        sensor: String,
+        value: f64,
+    }
+
+    #[test]
+    fn records_stable() {
+        // Negative keys too
+        let input: Vec<(i64, usize)> = keyed(10_000, 6, 200).into_iter()
+            .map(|(k, i)| (k as i64 - 100, i))
+            .collect();
+        let mut expected = input.clone();
+        expected.sort_by_key(|&(k, _)| k);
+
+        let mut v = input.clone();
+        radix_sort_by_key(&mut v, |&(k, _)| k);
+        assert_eq!(v, expected);
+
+        let mut v = input;
+        radix_sort_unstable_by_key(&mut v, |&(k, _)| k);
+        assert!(v.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        // Not Copy
+        let mut v: Vec<_> = [("a", 2.0), ("b", -1.0), ("c", 2.0), ("d", f64::NAN)]
+            .map(|(s, value)| Reading { sensor: s.to_string(), value })
+            .into();
+        radix_sort_by_key(&mut v, |r| r.value);
+        assert_eq!(v.iter().map(|r| r.sensor.as_str()).collect::<Vec<_>>(), ["b", "a", "c", "d"]);
+    }
+
+    #[test]
+    fn bytes() {
+        // Shared prefixes, empty strings and duplicates
+        let words = ["", "a", "ab", "abc", "abd", "b", "ba", "", "a", "z", "\u{e9}t\u{e9}", "\u{ff}"];
+        let mut v: Vec<String> = random(5_000, 7).into_iter()
+            .map(|r| {
+                let w = words[r as usize % words.len()];
+                format!("{w}{}", if r % 3 == 0 { "" } else { words[(r >> 8) as usize % words.len()] })
+            })
+            .collect();
+
+        let mut expected = v.clone();
+        expected.sort();
+        radix_sort_bytes(&mut v);
+        assert_eq!(v, expected);
+
+        // Stable, by a key
+        let input: Vec<(&str, usize)> = random(1_000, 8).into_iter()
+            .enumerate()
+            .map(|(i, r)| (words[r as usize % words.len()], i))
+            .collect();
+        let mut expected = input.clone();
+        expected.sort_by_key(|&(s, _)| s);
+        let mut v = input;
+        radix_sort_bytes_by_key(&mut v, |(s, _)| s.as_bytes());
+        assert_eq!(v, expected);
+    }
+
+    #[test]
+    fn long_common_prefix() {
+        // One bucket per shared byte, far deeper than the stack would go recursing
+        let prefix = "x".repeat(100_000);
+        let mut v: Vec<String> = random(100, 9).into_iter().map(|r| format!("{prefix}{r}")).collect();
+        let mut expected = v.clone();
+        expected.sort();
+        radix_sort_bytes(&mut v);
+        assert_eq!(v, expected);
+    }
+}
+
+#[cfg(test)]
+mod benches {
+    extern crate test;
+
+    use super::*;
+    use crate::sort::random;
+    use test::Bencher;
+
+    const N: usize = 200_000;
+
+    #[bench]
+    fn u64_sort_unstable(b: &mut Bencher) {
+        let input = random(N, 42);
+        b.iter(|| input.clone().sort_unstable());
+    }
+
+    #[bench]
+    fn u64_radix(b: &mut Bencher) {
+        let input = random(N, 42);
+        b.iter(|| radix_sort(&mut input.clone()));
+    }
+
+    #[bench]
+    fn u64_radix_unstable(b: &mut Bencher) {
+        let input = random(N, 42);
+        b.iter(|| radix_sort_unstable(&mut input.clone()));
+    }
+
+    #[bench]
+    fn f64_total_cmp(b: &mut Bencher) {
+        let input: Vec<f64> = random(N, 42).into_iter().map(|r| r as f64).collect();
+        b.iter(|| input.clone().sort_unstable_by(f64::total_cmp));
+    }
+
+    #[bench]
+    fn f64_radix(b: &mut Bencher) {
+        let input: Vec<f64> = random(N, 42).into_iter().map(|r| r as f64).collect();
+        b.iter(|| radix_sort(&mut input.clone()));
+    }
+}
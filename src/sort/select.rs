@@ -0,0 +1,219 @@
+/*
+ * The smallest or largest k of an iterator, in O(k) memory, and partial sorts of slices.
+ *
+ * bottom_k keeps a max-heap of the k smallest seen so far, so each new item is compared
+ * with the largest kept and either dropped or swapped in: O(n log k).
+ *
+ * Stable means the result is exactly the first k of a stable sort: equal items keep input
+ * order, and of equal items at the cut-off the earliest win. The heap entries carry their
+ * input position to break ties.
+ */
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Stability {
+    #[default]
+    Stable,
+    Unstable,
+}
+
+struct Entry<'a, T, F> {
+    item: T,
+    /// Input position, for Stable
+    seq: usize,
+    cmp: &'a F,
+    stability: Stability,
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> PartialEq for Entry<'_, T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> Eq for Entry<'_, T, F> {}
+
+impl<T, F: Fn(&T, &T) -> Ordering> PartialOrd for Entry<'_, T, F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T, F: Fn(&T, &T) -> Ordering> Ord for Entry<'_, T, F> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let o = (self.cmp)(&self.item, &other.item);
+        match self.stability {
+            Stability::Stable => o.then(self.seq.cmp(&other.seq)),
+            Stability::Unstable => o,
+        }
+    }
+}
+
+/// Stable, smallest first
+pub fn bottom_k<T: Ord>(iter: impl IntoIterator<Item = T>, k: usize) -> Vec<T> {
+    bottom_k_by(iter, k, Stability::Stable, T::cmp)
+}
+
+/// Stable, largest first
+pub fn top_k<T: Ord>(iter: impl IntoIterator<Item = T>, k: usize) -> Vec<T> {
+    top_k_by(iter, k, Stability::Stable, T::cmp)
+}
+
+pub fn bottom_k_by_key<T, K: Ord>(iter: impl IntoIterator<Item = T>, k: usize, key: impl Fn(&T) -> K) -> Vec<T> {
+    bottom_k_by(iter, k, Stability::Stable, |a, b| key(a).cmp(&key(b)))
+}
+
+pub fn top_k_by_key<T, K: Ord>(iter: impl IntoIterator<Item = T>, k: usize, key: impl Fn(&T) -> K) -> Vec<T> {
+    top_k_by(iter, k, Stability::Stable, |a, b| key(a).cmp(&key(b)))
+}
+
+/// The k smallest by `cmp`, in order
+pub fn bottom_k_by<T, F>(iter: impl IntoIterator<Item = T>, k: usize, stability: Stability, cmp: F) -> Vec<T>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    if k == 0 {
+        return vec![];
+    }
+
+    let cmp = &cmp;
+    let iter = iter.into_iter();
+    // k may be far more than there are items, even usize::MAX for all of them
+    let mut heap = BinaryHeap::with_capacity(k.min(iter.size_hint().0));
+    for (seq, item) in iter.enumerate() {
+        let entry = Entry { item, seq, cmp, stability };
+        if heap.len() < k {
+            heap.push(entry);
+        } else if let Some(mut max) = heap.peek_mut() {
+            // A later equal item is never smaller, so the earliest stay
+            if entry < *max {
+                *max = entry;
+            }
+        }
+    }
+
+    // into_sorted_vec is a heap sort, which isn't stable, but Stable entries never tie
+    heap.into_sorted_vec().into_iter().map(|e| e.item).collect()
+}
+
+/// The k largest by `cmp`, largest first. Stable keeps equal items in input order.
+pub fn top_k_by<T, F>(iter: impl IntoIterator<Item = T>, k: usize, stability: Stability, cmp: F) -> Vec<T>
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    bottom_k_by(iter, k, stability, |a, b| cmp(b, a))
+}
+
+/// Stable: puts the first k of `v.sort()` in `v[..k]`, leaving the rest in no particular order
+pub fn partial_sort<T: Ord>(v: &mut [T], k: usize) {
+    partial_sort_by(v, k, Stability::Stable, T::cmp);
+}
+
+pub fn partial_sort_by<T, F>(v: &mut [T], k: usize, stability: Stability, cmp: F)
+where
+    F: Fn(&T, &T) -> Ordering,
+{
+    let k = k.min(v.len());
+    if k == 0 {
+        return;
+    }
+
+    match stability {
+        Stability::Unstable => {
+            if k < v.len() {
+                v.select_nth_unstable_by(k - 1, &cmp);
+            }
+            v[..k].sort_unstable_by(&cmp);
+        }
+        Stability::Stable => {
+            // Select on indices, so ties can fall back to position, then move the chosen
+            // elements to the front
+            let indices = bottom_k_by(0..v.len(), k, Stability::Stable, |&i, &j| cmp(&v[i], &v[j]));
+            move_to_front(v, &indices);
+        }
+    }
+}
+
+/// Puts the element originally at `indices[j]` at `j`, in O(k) memory
+fn move_to_front<T>(v: &mut [T], indices: &[usize]) {
+    // Elements swapped out of the front: original index <-> where they are now
+    let mut location = HashMap::new();
+    let mut origin = HashMap::new();
+
+    for (j, &want) in indices.iter().enumerate() {
+        let src = location.remove(&want).unwrap_or(want);
+        let displaced = origin.remove(&j).unwrap_or(j);
+        if src != j {
+            v.swap(j, src);
+            location.insert(displaced, src);
+            origin.insert(src, displaced);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::{keyed, A1, A2, B0, B2};
+
+    #[test]
+    fn stable() {
+        assert_eq!(bottom_k([A2, B2, A1, B0], 3), [A2, A1, B2]);
+        // Largest first, still in input order among equals
+        assert_eq!(top_k([A2, B2, A1, B0], 3), [B2, B0, A2]);
+
+        let mut a = [B2, A2, B0, A1];
+        partial_sort(&mut a, 3);
+        assert_eq!(a[..3], [A2, A1, B2]);
+    }
+
+    #[test]
+    fn unstable() {
+        let v = bottom_k_by([A2, B2, A1, B0], 2, Stability::Unstable, Ord::cmp);
+        assert!(v.iter().all(|kv| kv.key == "A"));
+
+        let mut a = [B2, A2, B0, A1];
+        partial_sort_by(&mut a, 2, Stability::Unstable, Ord::cmp);
+        assert!(a[..2].iter().all(|kv| kv.key == "A"));
+    }
+
+    #[test]
+    fn same_as_sort() {
+        let input = keyed(1000, 3, 50);
+
+        let mut sorted = input.clone();
+        sorted.sort_by_key(|&(k, _)| k);
+        let mut sorted_desc = input.clone();
+        sorted_desc.sort_by_key(|&(k, _)| std::cmp::Reverse(k));
+
+        for k in [0, 1, 7, 50, 999, 1000, 2000, usize::MAX] {
+            let n = k.min(input.len());
+            let first = &sorted[..n];
+            assert_eq!(bottom_k_by_key(input.iter().copied(), k, |&(k, _)| k), first, "k {k}");
+            assert_eq!(top_k_by_key(input.iter().copied(), k, |&(k, _)| k), &sorted_desc[..n], "k {k}");
+
+            let mut v = input.clone();
+            partial_sort_by(&mut v, k, Stability::Stable, |a, b| a.0.cmp(&b.0));
+            assert_eq!(&v[..n], first, "k {k}");
+
+            let mut v = input.clone();
+            partial_sort_by(&mut v, k, Stability::Unstable, |a, b| a.0.cmp(&b.0));
+            assert_eq!(v[..n].iter().map(|&(k, _)| k).collect::<Vec<_>>(),
+                first.iter().map(|&(k, _)| k).collect::<Vec<_>>(), "k {k}");
+        }
+    }
+
+    #[test]
+    fn top_k_stable_ties() {
+        let top = top_k_by_key([(1, 'a'), (2, 'b'), (2, 'c'), (1, 'd'), (2, 'e')], 2, |&(k, _)| k);
+        assert_eq!(top, [(2, 'b'), (2, 'c')]);
+    }
+
+    #[test]
+    fn streaming() {
+        // Only k are ever held
+        let v = bottom_k((0..1_000_000_u64).rev(), 3);
+        assert_eq!(v, [0, 1, 2]);
+    }
+}
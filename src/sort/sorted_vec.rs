@@ -0,0 +1,475 @@
+/*
+ * Map and set kept sorted in Vecs, for lookup tables that are built once and read a lot.
+ *
+ * Lookups are binary searches over contiguous memory, and the map keeps keys and values in
+ * separate Vecs so a search only touches keys. Inserting one item is O(n); build in bulk
+ * with extend_with instead, which sorts the new items and merges them in O(n + m log m).
+ *
+ * Equality is the Ord of the key, so a set of KeyValue is keyed by `key` alone. What happens
+ * to equal keys in bulk inserts is a DedupPolicy, where "first" means existing items, then
+ * new ones in iteration order. KeepAll makes a multimap/multiset: use equal_range for those.
+ */
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Bound, Range, RangeBounds};
+
+/// Called with the item kept so far and each later equal one
+pub type MergeFn<'a, T> = Box<dyn FnMut(&mut T, T) + 'a>;
+
+pub enum DedupPolicy<'a, T> {
+    KeepFirst,
+    KeepLast,
+    KeepAll,
+    Merge(MergeFn<'a, T>),
+}
+
+impl<T> fmt::Debug for DedupPolicy<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DedupPolicy::KeepFirst => "KeepFirst",
+            DedupPolicy::KeepLast => "KeepLast",
+            DedupPolicy::KeepAll => "KeepAll",
+            DedupPolicy::Merge(_) => "Merge(..)",
+        })
+    }
+}
+
+/// Merges sorted `old` with unsorted `new`, stably, then applies the policy
+fn merge_dedup<E>(
+    old: Vec<E>,
+    new: impl IntoIterator<Item = E>,
+    cmp: impl Fn(&E, &E) -> Ordering,
+    policy: DedupPolicy<'_, E>,
+) -> Vec<E> {
+    let mut new: Vec<E> = new.into_iter().collect();
+    new.sort_by(&cmp);
+
+    let mut merged = Vec::with_capacity(old.len() + new.len());
+    let mut old = old.into_iter().peekable();
+    let mut new = new.into_iter().peekable();
+    loop {
+        // Old first on ties
+        let next = match (old.peek(), new.peek()) {
+            (Some(o), Some(n)) if cmp(n, o).is_lt() => new.next(),
+            (Some(_), _) => old.next(),
+            (None, _) => new.next(),
+        };
+        match next {
+            Some(e) => merged.push(e),
+            None => break,
+        }
+    }
+
+    let mut keep: MergeFn<'_, E> = match policy {
+        DedupPolicy::KeepAll => return merged,
+        DedupPolicy::KeepFirst => {
+            merged.dedup_by(|later, earlier| cmp(earlier, later).is_eq());
+            return merged;
+        }
+        DedupPolicy::KeepLast => Box::new(|kept, later| *kept = later),
+        DedupPolicy::Merge(f) => f,
+    };
+
+    let mut out: Vec<E> = Vec::with_capacity(merged.len());
+    for e in merged {
+        match out.last_mut() {
+            Some(kept) if cmp(kept, &e).is_eq() => keep(kept, e),
+            _ => out.push(e),
+        }
+    }
+    out
+}
+
+fn bounds_to_range<Q: ?Sized>(
+    range: &impl RangeBounds<Q>,
+    len: usize,
+    lower_bound: impl Fn(&Q) -> usize,
+    upper_bound: impl Fn(&Q) -> usize,
+) -> Range<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(q) => lower_bound(q),
+        Bound::Excluded(q) => upper_bound(q),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(q) => upper_bound(q),
+        Bound::Excluded(q) => lower_bound(q),
+        Bound::Unbounded => len,
+    };
+    // Empty rather than panicking for start > end
+    start..end.max(start)
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SortedVecMap<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+}
+
+impl<K: Ord, V> SortedVecMap<K, V> {
+    pub fn new() -> Self {
+        Self { keys: vec![], values: vec![] }
+    }
+
+    pub fn from_iter_with(iter: impl IntoIterator<Item = (K, V)>, policy: DedupPolicy<'_, V>) -> Self {
+        let mut map = Self::new();
+        map.extend_with(iter, policy);
+        map
+    }
+
+    pub fn extend_with(&mut self, iter: impl IntoIterator<Item = (K, V)>, policy: DedupPolicy<'_, V>) {
+        let policy = match policy {
+            DedupPolicy::KeepFirst => DedupPolicy::KeepFirst,
+            DedupPolicy::KeepLast => DedupPolicy::KeepLast,
+            DedupPolicy::KeepAll => DedupPolicy::KeepAll,
+            DedupPolicy::Merge(mut f) => DedupPolicy::Merge(Box::new(move |kept: &mut (K, V), (_, v)| f(&mut kept.1, v))),
+        };
+
+        let old = self.keys.drain(..).zip(self.values.drain(..)).collect();
+        let merged = merge_dedup(old, iter, |a, b| a.0.cmp(&b.0), policy);
+        (self.keys, self.values) = merged.into_iter().unzip();
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The first index whose key is >= `key`
+    pub fn lower_bound<Q: Ord + ?Sized>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+    {
+        self.keys.partition_point(|k| k.borrow() < key)
+    }
+
+    /// The first index whose key is > `key`
+    pub fn upper_bound<Q: Ord + ?Sized>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+    {
+        self.keys.partition_point(|k| k.borrow() <= key)
+    }
+
+    /// Indices of every entry with this key: more than one after KeepAll
+    pub fn equal_range<Q: Ord + ?Sized>(&self, key: &Q) -> Range<usize>
+    where
+        K: Borrow<Q>,
+    {
+        self.lower_bound(key)..self.upper_bound(key)
+    }
+
+    /// Finds the first entry with this key
+    fn find<Q: Ord + ?Sized>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+    {
+        let i = self.lower_bound(key);
+        (self.keys.get(i)?.borrow() == key).then_some(i)
+    }
+
+    pub fn get<Q: Ord + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.find(key).map(|i| &self.values[i])
+    }
+
+    pub fn get_mut<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+    {
+        self.find(key).map(|i| &mut self.values[i])
+    }
+
+    pub fn contains_key<Q: Ord + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.find(key).is_some()
+    }
+
+    /// O(n). Replaces the value of the first equal key, if any.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.find(&key) {
+            Some(i) => Some(std::mem::replace(&mut self.values[i], value)),
+            None => {
+                let i = self.upper_bound(&key);
+                self.keys.insert(i, key);
+                self.values.insert(i, value);
+                None
+            }
+        }
+    }
+
+    /// O(n). Removes the first entry with this key.
+    pub fn remove<Q: Ord + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let i = self.find(key)?;
+        self.keys.remove(i);
+        Some(self.values.remove(i))
+    }
+
+    pub fn range<Q: Ord + ?Sized>(&self, range: impl RangeBounds<Q>) -> impl Iterator<Item = (&K, &V)>
+    where
+        K: Borrow<Q>,
+    {
+        let r = bounds_to_range(&range, self.len(), |q| self.lower_bound(q), |q| self.upper_bound(q));
+        self.keys[r.clone()].iter().zip(&self.values[r])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.keys.iter().zip(&self.values)
+    }
+
+    pub fn keys(&self) -> &[K] {
+        &self.keys
+    }
+
+    pub fn values(&self) -> &[V] {
+        &self.values
+    }
+}
+
+impl<K: Ord, V> Default for SortedVecMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for SortedVecMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.keys.iter().zip(&self.values)).finish()
+    }
+}
+
+/// Keeps the last of equal keys, as `BTreeMap` does
+impl<K: Ord, V> FromIterator<(K, V)> for SortedVecMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self::from_iter_with(iter, DedupPolicy::KeepLast)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SortedVecSet<T> {
+    items: Vec<T>,
+}
+
+impl<T: Ord> SortedVecSet<T> {
+    pub fn new() -> Self {
+        Self { items: vec![] }
+    }
+
+    pub fn from_iter_with(iter: impl IntoIterator<Item = T>, policy: DedupPolicy<'_, T>) -> Self {
+        let mut set = Self::new();
+        set.extend_with(iter, policy);
+        set
+    }
+
+    pub fn extend_with(&mut self, iter: impl IntoIterator<Item = T>, policy: DedupPolicy<'_, T>) {
+        self.items = merge_dedup(std::mem::take(&mut self.items), iter, T::cmp, policy);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn lower_bound<Q: Ord + ?Sized>(&self, value: &Q) -> usize
+    where
+        T: Borrow<Q>,
+    {
+        self.items.partition_point(|t| t.borrow() < value)
+    }
+
+    pub fn upper_bound<Q: Ord + ?Sized>(&self, value: &Q) -> usize
+    where
+        T: Borrow<Q>,
+    {
+        self.items.partition_point(|t| t.borrow() <= value)
+    }
+
+    pub fn equal_range<Q: Ord + ?Sized>(&self, value: &Q) -> Range<usize>
+    where
+        T: Borrow<Q>,
+    {
+        self.lower_bound(value)..self.upper_bound(value)
+    }
+
+    /// The first stored item equal to `value`, which may differ in fields Ord ignores
+    pub fn get<Q: Ord + ?Sized>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+    {
+        self.items.get(self.lower_bound(value)).filter(|t| (*t).borrow() == value)
+    }
+
+    pub fn contains<Q: Ord + ?Sized>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+    {
+        self.get(value).is_some()
+    }
+
+    /// O(n). False, leaving the set unchanged, if an equal item is already there.
+    pub fn insert(&mut self, value: T) -> bool {
+        if self.contains(&value) {
+            return false;
+        }
+        let i = self.upper_bound(&value);
+        self.items.insert(i, value);
+        true
+    }
+
+    /// O(n). Removes the first equal item.
+    pub fn remove<Q: Ord + ?Sized>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+    {
+        let i = self.lower_bound(value);
+        if self.items.get(i)?.borrow() == value { Some(self.items.remove(i)) } else { None }
+    }
+
+    pub fn range<Q: Ord + ?Sized>(&self, range: impl RangeBounds<Q>) -> std::slice::Iter<'_, T>
+    where
+        T: Borrow<Q>,
+    {
+        let r = bounds_to_range(&range, self.len(), |q| self.lower_bound(q), |q| self.upper_bound(q));
+        self.items[r].iter()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+}
+
+impl<T: Ord> Default for SortedVecSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SortedVecSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(&self.items).finish()
+    }
+}
+
+/// Keeps the first of equal items, as `BTreeSet` does
+impl<T: Ord> FromIterator<T> for SortedVecSet<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_iter_with(iter, DedupPolicy::KeepFirst)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SortedVecSet<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::{KeyValue, NotEq, A1, A2, B0, B2};
+
+    fn values(set: &SortedVecSet<KeyValue>) -> Vec<(&str, f64)> {
+        set.iter().map(|kv| (kv.key, kv.value.0)).collect()
+    }
+
+    #[test]
+    fn set_policies() {
+        let existing = || SortedVecSet::from_iter([A1]);
+
+        let mut set = existing();
+        set.extend_with([B2, A2, B0], DedupPolicy::KeepFirst);
+        assert_eq!(values(&set), [("A", 1.0), ("B", 2.0)]);
+
+        let mut set = existing();
+        set.extend_with([B2, A2, B0], DedupPolicy::KeepLast);
+        assert_eq!(values(&set), [("A", 2.0), ("B", 0.0)]);
+
+        // Stable: existing first, then new in input order
+        let mut set = existing();
+        set.extend_with([B2, A2, B0], DedupPolicy::KeepAll);
+        assert_eq!(values(&set), [("A", 1.0), ("A", 2.0), ("B", 2.0), ("B", 0.0)]);
+        assert_eq!(set.equal_range(&B0), 2..4);
+
+        let mut set = existing();
+        set.extend_with([B2, A2, B0], DedupPolicy::Merge(Box::new(|kept: &mut KeyValue, kv: KeyValue| {
+            kept.value.0 += kv.value.0;
+        })));
+        assert_eq!(values(&set), [("A", 3.0), ("B", 2.0)]);
+    }
+
+    #[test]
+    fn set_lookup() {
+        let mut set: SortedVecSet<_> = [B2, A2, B0, A1].into_iter().collect();
+        assert_eq!(values(&set), [("A", 2.0), ("B", 2.0)]);
+
+        // Finds the stored item, by key only
+        let probe = KeyValue { key: "B", value: NotEq(99.0) };
+        assert_eq!(set.get(&probe).map(|kv| kv.value.0), Some(2.0));
+        assert!(!set.insert(B0));
+        assert!(set.insert(KeyValue { key: "C", value: NotEq(3.0) }));
+        assert_eq!(set.remove(&A1).map(|kv| kv.value.0), Some(2.0));
+        assert_eq!(values(&set), [("B", 2.0), ("C", 3.0)]);
+
+        let set: SortedVecSet<u32> = [5, 1, 3, 3, 9, 7].into_iter().collect();
+        assert_eq!(set.range(3..=7).copied().collect::<Vec<_>>(), [3, 5, 7]);
+        assert_eq!(set.range(4..).copied().collect::<Vec<_>>(), [5, 7, 9]);
+        assert_eq!(set.range((Bound::Included(8), Bound::Excluded(2))).count(), 0);
+        assert_eq!((set.lower_bound(&4), set.upper_bound(&5)), (2, 3));
+    }
+
+    #[test]
+    fn map() {
+        let mut map: SortedVecMap<String, u32> =
+            [("b", 2), ("a", 1), ("c", 3), ("a", 10)].map(|(k, v)| (k.to_string(), v)).into_iter().collect();
+        // Borrowed lookups, last value wins like BTreeMap
+        assert_eq!(map.get("a"), Some(&10));
+        assert_eq!(map.get("z"), None);
+        assert_eq!(map.keys(), ["a", "b", "c"]);
+
+        assert_eq!(map.insert("b".to_string(), 20), Some(2));
+        assert_eq!(map.insert("bb".to_string(), 22), None);
+        *map.get_mut("c").unwrap() += 1;
+        assert_eq!(map.remove("a"), Some(10));
+        assert_eq!(map.values(), [20, 22, 4]);
+
+        let range: Vec<_> = map.range::<str>((Bound::Excluded("b"), Bound::Unbounded)).map(|(k, v)| (k.as_str(), *v)).collect();
+        assert_eq!(range, [("bb", 22), ("c", 4)]);
+        assert_eq!(format!("{map:?}"), r#"{"b": 20, "bb": 22, "c": 4}"#);
+    }
+
+    #[test]
+    fn map_policies() {
+        let words = "the cat sat on the mat the end".split(' ');
+
+        let counts = SortedVecMap::from_iter_with(words.clone().map(|w| (w, 1)), DedupPolicy::Merge(Box::new(|n, m| *n += m)));
+        assert_eq!(counts.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+            [("cat", 1), ("end", 1), ("mat", 1), ("on", 1), ("sat", 1), ("the", 3)]);
+
+        // Multimap: positions of each word
+        let positions = SortedVecMap::from_iter_with(words.enumerate().map(|(i, w)| (w, i)), DedupPolicy::KeepAll);
+        let the = positions.equal_range("the");
+        assert_eq!(positions.values()[the], [0, 4, 6]);
+        assert_eq!(positions.get("the"), Some(&0));
+    }
+}
@@ -0,0 +1,311 @@
+/*
+ * Multi-key sort order for records, instead of hand-written then_with chains.
+ *
+ * A SortSpec is a list of keys, each an extractor with its own direction and, for Option
+ * keys, where None goes. None placement isn't flipped by the direction: nulls last means
+ * last for desc too, as in SQL's NULLS LAST. For floats, extract a TotalF64.
+ *
+ * The same spec can be parsed at runtime, e.g. "age:desc,name:asc", against a SortFields
+ * registry of named keys.
+ */
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Nulls {
+    First,
+    #[default]
+    Last,
+}
+
+/// A key comparison before direction and null placement are applied
+enum KeyOrdering {
+    /// Both present (or not an Option key)
+    Values(Ordering),
+    /// One or both None, with None < Some
+    Nulls(Ordering),
+}
+
+type Compare<T> = Arc<dyn Fn(&T, &T) -> KeyOrdering + Send + Sync>;
+
+fn values<T, K: Ord>(key: impl Fn(&T) -> K + Send + Sync + 'static) -> Compare<T> {
+    Arc::new(move |a, b| KeyOrdering::Values(key(a).cmp(&key(b))))
+}
+
+fn options<T, K: Ord>(key: impl Fn(&T) -> Option<K> + Send + Sync + 'static) -> Compare<T> {
+    Arc::new(move |a, b| match (key(a), key(b)) {
+        (Some(a), Some(b)) => KeyOrdering::Values(a.cmp(&b)),
+        (a, b) => KeyOrdering::Nulls(a.is_some().cmp(&b.is_some())),
+    })
+}
+
+struct Key<T> {
+    compare: Compare<T>,
+    direction: Direction,
+    nulls: Nulls,
+}
+
+impl<T> Key<T> {
+    fn cmp(&self, a: &T, b: &T) -> Ordering {
+        match (self.compare)(a, b) {
+            KeyOrdering::Values(o) if self.direction == Direction::Desc => o.reverse(),
+            KeyOrdering::Values(o) => o,
+            KeyOrdering::Nulls(o) if self.nulls == Nulls::Last => o.reverse(),
+            KeyOrdering::Nulls(o) => o,
+        }
+    }
+}
+
+// Not derived: derive would need T: Clone
+impl<T> Clone for Key<T> {
+    fn clone(&self) -> Self {
+        Self { compare: self.compare.clone(), direction: self.direction, nulls: self.nulls }
+    }
+}
+
+pub struct SortSpec<T> {
+    keys: Vec<Key<T>>,
+}
+
+impl<T> SortSpec<T> {
+    pub fn new() -> Self {
+        Self { keys: vec![] }
+    }
+
+    pub fn asc<K: Ord>(self, key: impl Fn(&T) -> K + Send + Sync + 'static) -> Self {
+        self.by(key, Direction::Asc)
+    }
+
+    pub fn desc<K: Ord>(self, key: impl Fn(&T) -> K + Send + Sync + 'static) -> Self {
+        self.by(key, Direction::Desc)
+    }
+
+    pub fn by<K: Ord>(self, key: impl Fn(&T) -> K + Send + Sync + 'static, direction: Direction) -> Self {
+        self.push(values(key), direction, Nulls::default())
+    }
+
+    pub fn by_option<K: Ord>(
+        self,
+        key: impl Fn(&T) -> Option<K> + Send + Sync + 'static,
+        direction: Direction,
+        nulls: Nulls,
+    ) -> Self {
+        self.push(options(key), direction, nulls)
+    }
+
+    fn push(mut self, compare: Compare<T>, direction: Direction, nulls: Nulls) -> Self {
+        self.keys.push(Key { compare, direction, nulls });
+        self
+    }
+
+    /// Parses comma-separated `field[:asc|desc][:nulls_first|nulls_last]`, defaulting to
+    /// asc and the field's registered null placement
+    pub fn parse(spec: &str, fields: &SortFields<T>) -> Result<Self, ParseSpecError> {
+        let mut keys = vec![];
+        for part in spec.split(',').map(str::trim) {
+            let mut parts = part.split(':').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            if name.is_empty() {
+                return Err(ParseSpecError::Empty);
+            }
+            let mut key = fields.get(name).ok_or_else(|| ParseSpecError::UnknownField(name.to_owned()))?;
+
+            for modifier in parts {
+                match modifier.to_ascii_lowercase().as_str() {
+                    "asc" => key.direction = Direction::Asc,
+                    "desc" => key.direction = Direction::Desc,
+                    "nulls_first" => key.nulls = Nulls::First,
+                    "nulls_last" => key.nulls = Nulls::Last,
+                    _ => return Err(ParseSpecError::BadModifier { field: name.to_owned(), modifier: modifier.to_owned() }),
+                }
+            }
+            keys.push(key);
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn compare(&self, a: &T, b: &T) -> Ordering {
+        self.keys.iter()
+            .map(|k| k.cmp(a, b))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    /// Stable
+    pub fn sort(&self, v: &mut [T]) {
+        v.sort_by(|a, b| self.compare(a, b));
+    }
+}
+
+impl<T> Default for SortSpec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Named keys that `SortSpec::parse` can refer to
+pub struct SortFields<T> {
+    fields: Vec<(&'static str, Key<T>)>,
+}
+
+impl<T> SortFields<T> {
+    pub fn new() -> Self {
+        Self { fields: vec![] }
+    }
+
+    pub fn field<K: Ord>(mut self, name: &'static str, key: impl Fn(&T) -> K + Send + Sync + 'static) -> Self {
+        self.fields.push((name, Key { compare: values(key), direction: Direction::Asc, nulls: Nulls::default() }));
+        self
+    }
+
+    pub fn option_field<K: Ord>(
+        mut self,
+        name: &'static str,
+        key: impl Fn(&T) -> Option<K> + Send + Sync + 'static,
+        nulls: Nulls,
+    ) -> Self {
+        self.fields.push((name, Key { compare: options(key), direction: Direction::Asc, nulls }));
+        self
+    }
+
+    /// Case-insensitive, like the spec modifiers
+    fn get(&self, name: &str) -> Option<Key<T>> {
+        self.fields.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, key)| key.clone())
+    }
+}
+
+impl<T> Default for SortFields<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseSpecError {
+    Empty,
+    UnknownField(String),
+    BadModifier { field: String, modifier: String },
+}
+
+impl fmt::Display for ParseSpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseSpecError::Empty => f.write_str("empty sort field"),
+            ParseSpecError::UnknownField(field) => write!(f, "unknown sort field: {field}"),
+            ParseSpecError::BadModifier { field, modifier } => write!(f,
+                "{field}: expected asc, desc, nulls_first or nulls_last, found {modifier}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseSpecError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::parallel::par_sort_by;
+    use crate::sort::total::TotalF64;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Person {
+        name: &'static str,
+        age: u8,
+        phones: Vec<&'static str>,
+        score: Option<f64>,
+    }
+
+    fn person(name: &'static str, age: u8, phones: usize, score: Option<f64>) -> Person {
+        Person { name, age, phones: vec!["+44 1234567"; phones], score }
+    }
+
+    fn people() -> Vec<Person> {
+        vec![
+            person("john", 43, 2, Some(1.5)),
+            person("Jane", 39, 1, None),
+            person("Jim", 43, 1, Some(f64::NAN)),
+            person("jim", 43, 0, Some(-0.5)),
+            person("Alice", 39, 3, None),
+        ]
+    }
+
+    fn names(v: &[Person]) -> Vec<&str> {
+        v.iter().map(|p| p.name).collect()
+    }
+
+    fn fields() -> SortFields<Person> {
+        SortFields::new()
+            .field("name", |p: &Person| p.name.to_lowercase())
+            .field("age", |p: &Person| p.age)
+            .field("phones", |p: &Person| p.phones.len())
+            .option_field("score", |p: &Person| p.score.map(TotalF64), Nulls::Last)
+    }
+
+    #[test]
+    fn builder() {
+        // Age descending, then name case folded, then phone count
+        let spec = SortSpec::new()
+            .desc(|p: &Person| p.age)
+            .asc(|p: &Person| p.name.to_lowercase())
+            .asc(|p: &Person| p.phones.len());
+
+        let mut v = people();
+        spec.sort(&mut v);
+        assert_eq!(names(&v), ["jim", "Jim", "john", "Alice", "Jane"]);
+    }
+
+    #[test]
+    fn nulls() {
+        let mut v = people();
+        SortSpec::new().by_option(|p: &Person| p.score.map(TotalF64), Direction::Asc, Nulls::Last).sort(&mut v);
+        assert_eq!(names(&v), ["jim", "john", "Jim", "Jane", "Alice"]);
+
+        // Desc doesn't move the nulls
+        SortSpec::new().by_option(|p: &Person| p.score.map(TotalF64), Direction::Desc, Nulls::Last).sort(&mut v);
+        assert_eq!(names(&v), ["Jim", "john", "jim", "Jane", "Alice"]);
+
+        SortSpec::new().by_option(|p: &Person| p.score.map(TotalF64), Direction::Desc, Nulls::First).sort(&mut v);
+        assert_eq!(names(&v), ["Jane", "Alice", "Jim", "john", "jim"]);
+    }
+
+    #[test]
+    fn parse() -> Result<(), ParseSpecError> {
+        let mut v = people();
+        SortSpec::parse("age:desc,name:asc, phones", &fields())?.sort(&mut v);
+        assert_eq!(names(&v), ["jim", "Jim", "john", "Alice", "Jane"]);
+
+        SortSpec::parse("Score:DESC:nulls_first,name", &fields())?.sort(&mut v);
+        assert_eq!(names(&v), ["Alice", "Jane", "Jim", "john", "jim"]);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors() {
+        let parse = |s| SortSpec::parse(s, &fields()).err();
+        assert_eq!(parse("age,"), Some(ParseSpecError::Empty));
+        assert_eq!(parse("height:desc"), Some(ParseSpecError::UnknownField("height".to_string())));
+        assert_eq!(
+            parse("age:down").map(|e| e.to_string()).as_deref(),
+            Some("age: expected asc, desc, nulls_first or nulls_last, found down")
+        );
+    }
+
+    #[test]
+    fn parallel() -> Result<(), ParseSpecError> {
+        let spec = SortSpec::parse("age:desc,name,phones", &fields())?;
+        let mut v = people();
+        par_sort_by(&mut v, |a, b| spec.compare(a, b));
+        assert_eq!(names(&v), ["jim", "Jim", "john", "Alice", "Jane"]);
+        Ok(())
+    }
+}
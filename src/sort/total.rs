@@ -0,0 +1,338 @@
+/*
+ * Floats usable as BTreeMap/HashSet keys and in #[derive(Ord, Hash)] structs.
+ *
+ * TotalF64/TotalF32 order by total_cmp, as in sort_floats_total: -NaN < -inf < ... < -0.0
+ * < 0.0 < ... < inf < NaN. Equality and Hash are by bit pattern, which agrees with that
+ * order: -0.0 != 0.0, and NaN == NaN only if the bits match.
+ *
+ * NotNan<f64>/NotNan<f32> instead keep IEEE equality (-0.0 == 0.0) by refusing NaN, on
+ * construction and as the result of arithmetic (which panics, like integer overflow).
+ */
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Rem, RemAssign, Sub, SubAssign};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TotalF64(pub f64);
+
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TotalF32(pub f32);
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NotNan<T>(T);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NanError;
+
+impl fmt::Display for NanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NaN not allowed")
+    }
+}
+
+impl std::error::Error for NanError {}
+
+macro_rules! binary_ops {
+    ($t:ty, $wrap:expr; $($op:ident $method:ident $op_assign:ident $method_assign:ident,)*) => {
+        $(
+            impl $op for $t {
+                type Output = $t;
+
+                fn $method(self, rhs: $t) -> $t {
+                    $wrap(self.0.$method(rhs.0))
+                }
+            }
+
+            impl $op_assign for $t {
+                fn $method_assign(&mut self, rhs: $t) {
+                    *self = self.$method(rhs);
+                }
+            }
+        )*
+
+        impl Neg for $t {
+            type Output = $t;
+
+            fn neg(self) -> $t {
+                $wrap(-self.0)
+            }
+        }
+    };
+}
+
+macro_rules! total_float {
+    ($t:ident, $f:ty) => {
+        impl PartialEq for $t {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.to_bits() == other.0.to_bits()
+            }
+        }
+
+        impl Eq for $t {}
+
+        impl Hash for $t {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.0.to_bits().hash(state);
+            }
+        }
+
+        impl PartialOrd for $t {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $t {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+
+        impl From<$f> for $t {
+            fn from(f: $f) -> Self {
+                $t(f)
+            }
+        }
+
+        impl From<$t> for $f {
+            fn from(t: $t) -> Self {
+                t.0
+            }
+        }
+
+        impl fmt::Display for $t {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        binary_ops! { $t, $t;
+            Add add AddAssign add_assign,
+            Sub sub SubAssign sub_assign,
+            Mul mul MulAssign mul_assign,
+            Div div DivAssign div_assign,
+            Rem rem RemAssign rem_assign,
+        }
+    };
+}
+
+total_float!(TotalF64, f64);
+total_float!(TotalF32, f32);
+
+macro_rules! not_nan {
+    ($f:ty) => {
+        impl NotNan<$f> {
+            pub fn new(f: $f) -> Result<Self, NanError> {
+                if f.is_nan() { Err(NanError) } else { Ok(NotNan(f)) }
+            }
+
+            pub fn get(self) -> $f {
+                self.0
+            }
+
+            // For arithmetic results
+            fn expect(f: $f) -> Self {
+                Self::new(f).expect("arithmetic on NotNan gave NaN")
+            }
+        }
+
+        impl PartialEq for NotNan<$f> {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+
+        impl Eq for NotNan<$f> {}
+
+        impl Hash for NotNan<$f> {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                // -0.0 == 0.0 so they must hash the same
+                let f = if self.0 == 0.0 { 0.0 } else { self.0 };
+                f.to_bits().hash(state);
+            }
+        }
+
+        impl PartialOrd for NotNan<$f> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for NotNan<$f> {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.partial_cmp(&other.0).expect("NotNan is never NaN")
+            }
+        }
+
+        impl TryFrom<$f> for NotNan<$f> {
+            type Error = NanError;
+
+            fn try_from(f: $f) -> Result<Self, NanError> {
+                Self::new(f)
+            }
+        }
+
+        impl From<NotNan<$f>> for $f {
+            fn from(n: NotNan<$f>) -> Self {
+                n.0
+            }
+        }
+
+        impl fmt::Display for NotNan<$f> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl Serialize for NotNan<$f> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.0.serialize(serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for NotNan<$f> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Self::new(<$f>::deserialize(deserializer)?).map_err(de::Error::custom)
+            }
+        }
+
+        binary_ops! { NotNan<$f>, NotNan::<$f>::expect;
+            Add add AddAssign add_assign,
+            Sub sub SubAssign sub_assign,
+            Mul mul MulAssign mul_assign,
+            Div div DivAssign div_assign,
+            Rem rem RemAssign rem_assign,
+        }
+    };
+}
+
+not_nan!(f64);
+not_nan!(f32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, HashSet};
+
+    use key_ord::KeyOrd;
+
+    #[test]
+    fn total_order() {
+        let mut a = [5.3_f64, 2.6, 0.0, f64::NEG_INFINITY, f64::INFINITY, -2.4e32, f64::NAN, -0.0, -f64::NAN]
+            .map(TotalF64);
+        a.sort();
+
+        // As sort_floats_total
+        let last = a.len() - 1;
+        assert!(a[0].0.is_nan() && a[0].0.is_sign_negative());
+        assert!(a[last].0.is_nan() && a[last].0.is_sign_positive());
+        assert_eq!(a[1..last].iter().map(|t| t.0).collect::<Vec<_>>(),
+            [-f64::INFINITY, -2.4e32, -0.0, 0.0, 2.6, 5.3_f64, f64::INFINITY]);
+    }
+
+    #[test]
+    fn total_eq() {
+        assert_eq!(TotalF64(f64::NAN), TotalF64(f64::NAN));
+        assert_ne!(TotalF64(f64::NAN), TotalF64(-f64::NAN));
+        assert_ne!(TotalF64(0.0), TotalF64(-0.0));
+        assert_eq!(TotalF32(1.5), TotalF32(1.5));
+    }
+
+    #[test]
+    fn total_map_keys() {
+        let mut m = BTreeMap::new();
+        m.insert(TotalF64(f64::NAN), "nan");
+        m.insert(TotalF64(1.0), "one");
+        m.insert(TotalF64(-0.0), "minus zero");
+        m.insert(TotalF64(0.0), "zero");
+        assert_eq!(m.values().copied().collect::<Vec<_>>(), ["minus zero", "zero", "one", "nan"]);
+        assert_eq!(m.get(&TotalF64(f64::NAN)), Some(&"nan"));
+
+        let s: HashSet<_> = [0.0, -0.0, f64::NAN, f64::NAN, 1.0].map(TotalF64).into();
+        assert_eq!(s.len(), 4);
+    }
+
+    // KeyValue's NotEq(f64) value, but now it can be part of the key or derived
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    struct KeyValue {
+        key: &'static str,
+        value: TotalF64,
+    }
+
+    #[derive(Debug, Clone, Copy, KeyOrd)]
+    struct Keyed {
+        #[key]
+        value: TotalF32,
+    }
+
+    #[test]
+    fn derive() {
+        let mut a = [
+            KeyValue { key: "B", value: TotalF64(0.0) },
+            KeyValue { key: "A", value: TotalF64(2.0) },
+            KeyValue { key: "A", value: TotalF64(f64::NAN) },
+            KeyValue { key: "A", value: TotalF64(1.0) },
+        ];
+        a.sort();
+        assert_eq!(a.map(|kv| (kv.key, kv.value.0.to_string())),
+            [("A", "1".into()), ("A", "2".into()), ("A", "NaN".into()), ("B", "0".into())]);
+
+        assert!(Keyed { value: TotalF32(-0.0) } < Keyed { value: TotalF32(0.0) });
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut x = TotalF64(1.5) + TotalF64(2.0) * TotalF64(3.0);
+        assert_eq!(x, TotalF64(7.5));
+        x -= TotalF64(0.5);
+        x /= TotalF64(2.0);
+        assert_eq!(-x, TotalF64(-3.5));
+        assert_eq!(TotalF32(7.0) % TotalF32(4.0), TotalF32(3.0));
+        // NaN is just another value
+        assert!((TotalF64(f64::INFINITY) - TotalF64(f64::INFINITY)).0.is_nan());
+    }
+
+    #[test]
+    fn not_nan() {
+        assert_eq!(NotNan::<f64>::new(f64::NAN), Err(NanError));
+        assert_eq!(NotNan::try_from(f32::NAN), Err(NanError));
+
+        let zero = NotNan::<f64>::new(0.0_f64).unwrap();
+        let minus_zero = NotNan::<f64>::new(-0.0_f64).unwrap();
+        // IEEE equality, unlike TotalF64
+        assert_eq!(zero, minus_zero);
+        let s: HashSet<_> = [zero, minus_zero].into();
+        assert_eq!(s.len(), 1);
+
+        let mut a = [3.0, f64::NEG_INFINITY, -1.0, f64::INFINITY].map(|f| NotNan::<f64>::new(f).unwrap());
+        a.sort();
+        assert_eq!(a.map(NotNan::<f64>::get), [f64::NEG_INFINITY, -1.0, 3.0, f64::INFINITY]);
+
+        let x = NotNan::<f64>::new(1.5_f64).unwrap() + NotNan::<f64>::new(2.0).unwrap();
+        assert_eq!(x.get(), 3.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "arithmetic on NotNan gave NaN")]
+    fn not_nan_arithmetic_nan() {
+        let inf = NotNan::<f64>::new(f64::INFINITY).unwrap();
+        let _ = inf - inf;
+    }
+
+    #[test]
+    fn serde_transparent() -> Result<(), serde_json::Error> {
+        assert_eq!(serde_json::to_string(&TotalF64(1.5))?, "1.5");
+        assert_eq!(serde_json::from_str::<TotalF32>("-2.25")?, TotalF32(-2.25));
+
+        assert_eq!(serde_json::to_string(&NotNan::<f64>::new(1.5_f64).unwrap())?, "1.5");
+        assert_eq!(serde_json::from_str::<NotNan<f64>>("2")?.get(), 2.0);
+        // JSON has no NaN; null is the nearest
+        assert!(serde_json::from_str::<NotNan<f64>>("null").is_err());
+        Ok(())
+    }
+}